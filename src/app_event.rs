@@ -0,0 +1,111 @@
+use crate::file_reader::{FileReader, ProgressCallback, SearchMatch};
+use crate::formatter::FormattedSource;
+use crate::structure::StructureIndex;
+use anyhow::Result;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// How a piped command exited. Its standard output is in a file, as it may be any size.
+pub struct CommandOutput {
+    pub status: std::process::ExitStatus,
+    pub path: PathBuf,
+    pub stderr: String,
+}
+
+/// Everything the main loop reacts to. Terminal input, background task progress and
+/// file-change notifications are all merged into a single channel of these.
+pub enum AppEvent {
+    Input(crossterm::event::Event),
+    /// Progress of the background task with the given id, or of the initial load with none
+    Progress {
+        task_id: Option<u64>,
+        progress: f64,
+        message: String,
+    },
+    Loaded(Result<FileReader>),
+    Formatted {
+        task_id: u64,
+        result: Result<FormattedSource>,
+    },
+    SearchComplete {
+        task_id: u64,
+        search_term: String,
+        matches: Vec<SearchMatch>,
+    },
+    StructureIndexed {
+        task_id: u64,
+        index: StructureIndex,
+    },
+    /// A command the selection was piped to has exited
+    CommandFinished {
+        task_id: u64,
+        command: String,
+        result: Result<CommandOutput>,
+    },
+    /// Time to scroll again while a selection is dragged past the edge of the view
+    DragScroll {
+        task_id: u64,
+    },
+    FileChanged,
+}
+
+impl AppEvent {
+    /// The background task the event comes from, if any
+    pub fn task_id(&self) -> Option<u64> {
+        match self {
+            AppEvent::Progress { task_id, .. } => *task_id,
+            AppEvent::Formatted { task_id, .. }
+            | AppEvent::SearchComplete { task_id, .. }
+            | AppEvent::StructureIndexed { task_id, .. }
+            | AppEvent::CommandFinished { task_id, .. }
+            | AppEvent::DragScroll { task_id } => Some(*task_id),
+            AppEvent::Input(_) | AppEvent::Loaded(_) | AppEvent::FileChanged => None,
+        }
+    }
+    
+    /// Whether handling this event can change what is on screen
+    pub fn affects_display(&self) -> bool {
+        !matches!(
+            self,
+            AppEvent::Input(crossterm::event::Event::Mouse(crossterm::event::MouseEvent {
+                kind: crossterm::event::MouseEventKind::Moved,
+                ..
+            }))
+        )
+    }
+}
+
+/// Build a progress callback that forwards updates from a task to the event channel
+pub fn progress_sender(events: &Sender<AppEvent>, task_id: Option<u64>) -> ProgressCallback {
+    let events = events.clone();
+    Box::new(move |progress, message| {
+        let _ = events.send(AppEvent::Progress { task_id, progress, message: message.to_string() });
+    })
+}
+
+/// Forward terminal input to the event channel from a dedicated thread
+pub fn spawn_input_reader(events: Sender<AppEvent>) {
+    thread::spawn(move || {
+        while let Ok(event) = crossterm::event::read() {
+            if events.send(AppEvent::Input(event)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Watch a file for modifications. The watcher stops when the returned value is dropped.
+pub fn watch_file(path: &Path, events: Sender<AppEvent>) -> Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        if let Ok(event) = result {
+            if event.kind.is_modify() || event.kind.is_create() {
+                let _ = events.send(AppEvent::FileChanged);
+            }
+        }
+    })?;
+    
+    watcher.watch(path, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
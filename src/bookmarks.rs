@@ -0,0 +1,143 @@
+use crate::constants::Constants;
+use std::collections::BTreeMap;
+
+/// Vim-style named marks, set with `m{a-z}` and jumped to with `'{a-z}`
+#[derive(Debug, Default)]
+pub struct Marks {
+    marks: BTreeMap<char, usize>,
+}
+
+impl Marks {
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_lowercase()
+    }
+    
+    pub fn set(&mut self, name: char, line: usize) {
+        self.marks.insert(name, line);
+    }
+    
+    pub fn get(&self, name: char) -> Option<usize> {
+        self.marks.get(&name).copied()
+    }
+    
+    /// The first mark name not in use yet
+    pub fn first_free(&self) -> Option<char> {
+        ('a'..='z').find(|name| !self.marks.contains_key(name))
+    }
+    
+    /// The first mark placed on a line, used for the gutter
+    pub fn mark_at(&self, line: usize) -> Option<char> {
+        self.marks.iter().find(|&(_, &mark_line)| mark_line == line).map(|(&name, _)| name)
+    }
+    
+    /// Move every mark to the equivalent line after the file is shown differently
+    pub fn remap(&mut self, translate: impl Fn(usize) -> usize) {
+        for line in self.marks.values_mut() {
+            *line = translate(*line);
+        }
+    }
+}
+
+/// History of large jumps with back and forward navigation, like vim's jump list
+#[derive(Debug, Default)]
+pub struct JumpList {
+    entries: Vec<usize>,
+    position: usize,
+}
+
+impl JumpList {
+    /// Remember the line a jump started from. Any forward history is discarded.
+    pub fn record(&mut self, line: usize) {
+        self.entries.truncate(self.position);
+        if self.entries.last() != Some(&line) {
+            self.entries.push(line);
+        }
+        
+        if self.entries.len() > Constants::JUMP_LIST_CAPACITY {
+            self.entries.remove(0);
+        }
+        self.position = self.entries.len();
+    }
+    
+    /// Step back in history. The current line is remembered first, so that
+    /// `forward` can return to it.
+    pub fn back(&mut self, current: usize) -> Option<usize> {
+        if self.position == self.entries.len() {
+            self.record(current);
+            self.position = self.entries.len() - 1;
+        }
+        
+        if self.position == 0 {
+            return None;
+        }
+        self.position -= 1;
+        Some(self.entries[self.position])
+    }
+    
+    pub fn forward(&mut self) -> Option<usize> {
+        if self.position + 1 >= self.entries.len() {
+            return None;
+        }
+        self.position += 1;
+        Some(self.entries[self.position])
+    }
+    
+    /// Move every entry to the equivalent line after the file is shown differently
+    pub fn remap(&mut self, translate: impl Fn(usize) -> usize) {
+        for line in &mut self.entries {
+            *line = translate(*line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_marks() {
+        let mut marks = Marks::default();
+        marks.set('a', 10);
+        marks.set('b', 10);
+        marks.set('a', 20);
+        assert_eq!(marks.get('a'), Some(20));
+        assert_eq!(marks.get('c'), None);
+        assert_eq!(marks.mark_at(10), Some('b'));
+        assert_eq!(marks.mark_at(20), Some('a'));
+        assert!(Marks::is_valid_name('z'));
+        assert!(!Marks::is_valid_name('A'));
+        assert_eq!(marks.first_free(), Some('c'));
+        
+        marks.remap(|line| line * 2);
+        assert_eq!(marks.get('a'), Some(40));
+        assert_eq!(marks.mark_at(20), Some('b'));
+    }
+    
+    #[test]
+    fn test_jump_list_back_and_forward() {
+        let mut jumps = JumpList::default();
+        jumps.record(10);
+        jumps.record(500);
+        
+        // Currently at 900 after the second jump
+        assert_eq!(jumps.back(900), Some(500));
+        assert_eq!(jumps.back(500), Some(10));
+        assert_eq!(jumps.back(10), None);
+        assert_eq!(jumps.forward(), Some(500));
+        assert_eq!(jumps.forward(), Some(900));
+        assert_eq!(jumps.forward(), None);
+    }
+    
+    #[test]
+    fn test_jump_list_discards_forward_history() {
+        let mut jumps = JumpList::default();
+        jumps.record(10);
+        assert_eq!(jumps.back(500), Some(10));
+        
+        // A new jump from 10 replaces the entry for 500
+        jumps.record(10);
+        assert_eq!(jumps.forward(), None);
+        assert_eq!(jumps.back(700), Some(10));
+        assert_eq!(jumps.forward(), Some(700));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{constants::Constants, text_utils::TextUtils};
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use clipboard::{ClipboardContext, ClipboardProvider};
+use std::io::Write;
+
+/// Where copied text goes, and where pasted text comes from.
+///
+/// The system clipboard needs a local display, so over SSH or on a headless machine copies
+/// are sent to the terminal instead, as an OSC 52 escape sequence. The terminal never says
+/// whether it accepted one, and can't be asked for its contents, so pasting relies on the
+/// terminal's own paste in that case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardBackend {
+    System,
+    Osc52,
+}
+
+impl ClipboardBackend {
+    /// The backend named by `BIGVIEW_CLIPBOARD` (`system` or `osc52`), otherwise OSC 52 in an
+    /// SSH session or when there is no X11 or Wayland display to reach
+    pub fn detect() -> Self {
+        if let Some(backend) = std::env::var("BIGVIEW_CLIPBOARD").ok().and_then(|name| Self::from_name(&name)) {
+            return backend;
+        }
+        
+        let var_set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        let remote = var_set("SSH_CONNECTION") || var_set("SSH_TTY");
+        let headless = cfg!(all(unix, not(target_os = "macos"))) && !var_set("DISPLAY") && !var_set("WAYLAND_DISPLAY");
+        if remote || headless {
+            ClipboardBackend::Osc52
+        } else {
+            ClipboardBackend::System
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "system" => Some(ClipboardBackend::System),
+            "osc52" => Some(ClipboardBackend::Osc52),
+            _ => None,
+        }
+    }
+    
+    /// What the clipboard is called in status messages
+    pub fn description(self) -> &'static str {
+        match self {
+            ClipboardBackend::System => "clipboard",
+            ClipboardBackend::Osc52 => "terminal clipboard",
+        }
+    }
+    
+    pub fn copy(self, text: &str) -> Result<()> {
+        match self {
+            ClipboardBackend::System => {
+                let mut context: ClipboardContext = ClipboardProvider::new().map_err(|e| anyhow!("{}", e))?;
+                context.set_contents(text.to_string()).map_err(|e| anyhow!("{}", e))
+            }
+            ClipboardBackend::Osc52 => {
+                self.check_size(text.len())?;
+                let mut stdout = std::io::stdout();
+                stdout.write_all(Self::osc52_sequence(text).as_bytes())?;
+                stdout.flush()?;
+                Ok(())
+            }
+        }
+    }
+    
+    /// Refuse a copy of `bytes` bytes that the clipboard can't take, before the text is built
+    pub fn check_size(self, bytes: usize) -> Result<()> {
+        if self == ClipboardBackend::Osc52 && bytes > Constants::OSC52_MAX_BYTES {
+            bail!("{} bytes is too much to send to the terminal clipboard", TextUtils::format_count(bytes));
+        }
+        Ok(())
+    }
+    
+    pub fn paste(self) -> Result<String> {
+        match self {
+            ClipboardBackend::System => {
+                let mut context: ClipboardContext = ClipboardProvider::new().map_err(|e| anyhow!("{}", e))?;
+                context.get_contents().map_err(|e| anyhow!("{}", e))
+            }
+            ClipboardBackend::Osc52 => bail!("The terminal clipboard can only be pasted with the terminal's paste"),
+        }
+    }
+    
+    /// The escape sequence that sets the terminal's clipboard to `text`
+    fn osc52_sequence(text: &str) -> String {
+        format!("\x1b]52;c;{}\x07", base64::engine::general_purpose::STANDARD.encode(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(ClipboardBackend::osc52_sequence("héllo\n"), "\x1b]52;c;aMOpbGxvCg==\x07");
+        assert_eq!(ClipboardBackend::from_name(" OSC52"), Some(ClipboardBackend::Osc52));
+        assert_eq!(ClipboardBackend::from_name("x11"), None);
+    }
+}
@@ -4,11 +4,11 @@ pub struct Constants;
 impl Constants {
     // UI Layout
     pub const LINE_NUMBER_WIDTH: u16 = 7;
-    pub const CONTEXT_MENU_WIDTH: u16 = 10;
     pub const SCROLL_LINES_PER_WHEEL: usize = 3;
     
     // Colors and Styles
     pub const LINE_NUMBER_COLOR: ratatui::style::Color = ratatui::style::Color::Yellow;
+    pub const MARK_COLOR: ratatui::style::Color = ratatui::style::Color::LightMagenta;
     pub const SELECTION_BG_COLOR: ratatui::style::Color = ratatui::style::Color::Blue;
     pub const SELECTION_FG_COLOR: ratatui::style::Color = ratatui::style::Color::White;
     pub const CURRENT_MATCH_BG_COLOR: ratatui::style::Color = ratatui::style::Color::Cyan;
@@ -17,18 +17,74 @@ impl Constants {
     pub const OTHER_MATCH_FG_COLOR: ratatui::style::Color = ratatui::style::Color::White;
     pub const CONTEXT_MENU_BG_COLOR: ratatui::style::Color = ratatui::style::Color::DarkGray;
     pub const CONTEXT_MENU_FG_COLOR: ratatui::style::Color = ratatui::style::Color::White;
+    pub const CONTEXT_MENU_SELECTED_BG_COLOR: ratatui::style::Color = ratatui::style::Color::Blue;
+    pub const CONTEXT_MENU_SELECTED_FG_COLOR: ratatui::style::Color = ratatui::style::Color::White;
+    pub const HIGHLIGHT_BG_COLOR: ratatui::style::Color = ratatui::style::Color::Yellow;
+    pub const HIGHLIGHT_FG_COLOR: ratatui::style::Color = ratatui::style::Color::Black;
     pub const STATUS_BAR_BG_COLOR: ratatui::style::Color = ratatui::style::Color::Blue;
     pub const STATUS_BAR_FG_COLOR: ratatui::style::Color = ratatui::style::Color::White;
+    pub const FORMAT_ERROR_BG_COLOR: ratatui::style::Color = ratatui::style::Color::Red;
+    pub const FORMAT_ERROR_FG_COLOR: ratatui::style::Color = ratatui::style::Color::White;
     
-    // Context Menu
-    pub const CONTEXT_MENU_COPY: &'static str = "Copy";
-    pub const CONTEXT_MENU_SEARCH: &'static str = "Search";
+    // Syntax Highlighting
+    pub const SYNTAX_KEY_COLOR: ratatui::style::Color = ratatui::style::Color::LightBlue;
+    pub const SYNTAX_STRING_COLOR: ratatui::style::Color = ratatui::style::Color::Green;
+    pub const SYNTAX_NUMBER_COLOR: ratatui::style::Color = ratatui::style::Color::Cyan;
+    pub const SYNTAX_BOOLEAN_COLOR: ratatui::style::Color = ratatui::style::Color::Magenta;
+    pub const SYNTAX_NULL_COLOR: ratatui::style::Color = ratatui::style::Color::LightRed;
+    pub const SYNTAX_TAG_COLOR: ratatui::style::Color = ratatui::style::Color::LightBlue;
+    pub const SYNTAX_ATTRIBUTE_COLOR: ratatui::style::Color = ratatui::style::Color::Cyan;
+    pub const SYNTAX_TEXT_COLOR: ratatui::style::Color = ratatui::style::Color::LightYellow;
+    pub const SYNTAX_COMMENT_COLOR: ratatui::style::Color = ratatui::style::Color::DarkGray;
+    pub const SYNTAX_HIGHLIGHT_MAX_BYTES: usize = 4096;
+    
+    // Selection
+    pub const MULTI_CLICK_INTERVAL_MS: u64 = 400;
+    /// Characters that join letters and digits into one word for double-click, so that
+    /// UUIDs, IP addresses and times are selected whole
+    pub const DEFAULT_WORD_CHARS: &'static str = "-.:";
+    
+    // Clipboard
+    /// Terminals cap the size of an OSC 52 sequence, and a huge one floods the connection
+    pub const OSC52_MAX_BYTES: usize = 1024 * 1024;
     
     // Progress Bar
     pub const PROGRESS_BAR_BG_COLOR: ratatui::style::Color = ratatui::style::Color::DarkGray;
     pub const PROGRESS_BAR_FG_COLOR: ratatui::style::Color = ratatui::style::Color::Green;
     pub const PROGRESS_BAR_HEIGHT: u16 = 3;
     
+    // Background Work
+    pub const LOAD_PROGRESS_MIN_BYTES: u64 = 10 * 1024 * 1024;
+    pub const SEARCH_PROGRESS_MIN_LINES: usize = 100_000;
+    pub const REFRESH_TAIL_CHECK_BYTES: usize = 4096;
+    pub const STRUCTURE_CANCEL_CHECK_LINES: usize = 65_536;
+    pub const DRAG_SCROLL_INTERVAL_MS: u64 = 50;
+    /// A command the selection is piped to is killed if it runs longer than this
+    pub const PIPE_COMMAND_TIMEOUT_SECS: u64 = 60;
+    pub const PIPE_COMMAND_POLL_MS: u64 = 50;
+    
+    // Format Detection
+    pub const FORMAT_SNIFF_BYTES: usize = 8 * 1024;
+    
+    // Formatting
+    /// How far ahead of the previous key a re-serializing formatter looks for the next one
+    pub const FORMAT_ANCHOR_SEARCH_BYTES: usize = 64 * 1024;
+    
+    // Format Cache
+    pub const FORMAT_CACHE_MAX_AGE_DAYS: u64 = 7;
+    pub const FORMAT_CACHE_HASH_CHUNK: usize = 1024 * 1024;
+    
+    // Table View
+    pub const TABLE_MAX_COLUMN_WIDTH: usize = 40;
+    pub const TABLE_HEADER_COLOR: ratatui::style::Color = ratatui::style::Color::LightCyan;
+    pub const TABLE_SEPARATOR_COLOR: ratatui::style::Color = ratatui::style::Color::DarkGray;
+    
+    // Folding
+    pub const FOLD_SUMMARY_COLOR: ratatui::style::Color = ratatui::style::Color::DarkGray;
+    
+    // Navigation
+    pub const JUMP_LIST_CAPACITY: usize = 100;
+    
     // Default Values
     pub const DEFAULT_VIEWPORT_HEIGHT: usize = 20;
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use ratatui::layout::Rect;
+
+/// Something the context menu can do with the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Copy,
+    CopyWithLineNumbers,
+    Search,
+    /// Show only the lines containing the selected text
+    Filter,
+    /// Mark every occurrence of the selected text, separately from the search
+    Highlight,
+    SetBookmark,
+    SaveToFile,
+    PipeToCommand,
+}
+
+impl MenuAction {
+    /// The actions in the order the menu lists them
+    pub const ALL: [MenuAction; 8] = [
+        MenuAction::Copy,
+        MenuAction::CopyWithLineNumbers,
+        MenuAction::Search,
+        MenuAction::Filter,
+        MenuAction::Highlight,
+        MenuAction::SetBookmark,
+        MenuAction::SaveToFile,
+        MenuAction::PipeToCommand,
+    ];
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuAction::Copy => "Copy",
+            MenuAction::CopyWithLineNumbers => "Copy with line numbers",
+            MenuAction::Search => "Search",
+            MenuAction::Filter => "Filter lines containing",
+            MenuAction::Highlight => "Highlight all",
+            MenuAction::SetBookmark => "Set bookmark here",
+            MenuAction::SaveToFile => "Save to file...",
+            MenuAction::PipeToCommand => "Pipe to command...",
+        }
+    }
+}
+
+/// Actions on the selection, opened with a right click or Enter. Items are picked with a
+/// click, or with the arrow keys and Enter.
+#[derive(Debug, Clone)]
+pub struct ContextMenu {
+    pub x: u16,
+    pub y: u16,
+    pub items: Vec<MenuAction>,
+    /// The item Enter picks
+    pub selected: usize,
+}
+
+impl ContextMenu {
+    pub fn new(x: u16, y: u16) -> Self {
+        Self {
+            x,
+            y,
+            items: MenuAction::ALL.to_vec(),
+            selected: 0,
+        }
+    }
+    
+    /// Move the highlight by `delta` items, wrapping around at either end
+    pub fn move_selection(&mut self, delta: isize) {
+        let count = self.items.len() as isize;
+        if count > 0 {
+            self.selected = (self.selected as isize + delta).rem_euclid(count) as usize;
+        }
+    }
+    
+    pub fn selected_action(&self) -> Option<MenuAction> {
+        self.items.get(self.selected).copied()
+    }
+    
+    /// Where the menu is drawn: at the click, moved left or up as far as needed to fit on screen
+    pub fn area(&self, screen: Rect) -> Rect {
+        let longest = self.items.iter().map(|item| item.label().len()).max().unwrap_or(0);
+        let width = (longest as u16 + 2).min(screen.width);
+        let height = (self.items.len() as u16).min(screen.height);
+        Rect {
+            x: self.x.min(screen.width - width),
+            y: self.y.min(screen.height - height),
+            width,
+            height,
+        }
+    }
+    
+    /// The index of the item drawn at a screen position
+    pub fn item_at(&self, screen: Rect, col: u16, row: u16) -> Option<usize> {
+        let area = self.area(screen);
+        let inside = col >= area.x && col < area.x + area.width && row >= area.y && row < area.y + area.height;
+        inside.then(|| (row - area.y) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_menu_layout_and_selection() {
+        let screen = Rect::new(0, 0, 80, 24);
+        let mut menu = ContextMenu::new(75, 20);
+        
+        // Moved back onto the screen, and sized to the longest label
+        let area = menu.area(screen);
+        assert_eq!((area.x, area.y, area.width, area.height), (55, 16, 25, 8));
+        assert_eq!(menu.item_at(screen, 60, 18), Some(2));
+        assert_eq!(menu.item_at(screen, 75, 15), None);
+        
+        menu.move_selection(-1);
+        assert_eq!(menu.selected_action(), Some(MenuAction::PipeToCommand));
+        menu.move_selection(2);
+        assert_eq!(menu.selected_action(), Some(MenuAction::CopyWithLineNumbers));
+    }
+}
@@ -1,20 +1,69 @@
 use crossterm::event::{Event, KeyCode, KeyModifiers, MouseButton, MouseEventKind};
-use crate::viewer::{Viewer, ViewerAction};
+use crate::app_event::AppEvent;
+use crate::selection::VisualMode;
+use crate::viewer::{MarkAction, Viewer, ViewerAction};
 
 pub struct EventHandler;
 
 impl EventHandler {
+    pub fn handle_app_event(viewer: &mut Viewer, event: AppEvent) -> ViewerAction {
+        match event {
+            AppEvent::Input(event) => Self::handle_event(viewer, event),
+            AppEvent::Progress { progress, message, .. } => {
+                viewer.update_progress(progress, &message);
+                ViewerAction::None
+            }
+            AppEvent::SearchComplete { task_id, search_term, matches } => {
+                viewer.finish_search(task_id, search_term, matches);
+                ViewerAction::None
+            }
+            AppEvent::Formatted { task_id, result } => {
+                viewer.finish_formatting(task_id, result);
+                ViewerAction::None
+            }
+            AppEvent::StructureIndexed { task_id, index } => {
+                viewer.finish_structure_index(task_id, index);
+                ViewerAction::None
+            }
+            AppEvent::CommandFinished { task_id, command, result } => viewer.finish_command(task_id, &command, result),
+            AppEvent::DragScroll { task_id } => {
+                viewer.drag_scroll(task_id);
+                ViewerAction::None
+            }
+            AppEvent::FileChanged => {
+                viewer.reload_file();
+                ViewerAction::None
+            }
+            AppEvent::Loaded(_) => ViewerAction::None,
+        }
+    }
+    
     pub fn handle_event(viewer: &mut Viewer, event: Event) -> ViewerAction {
         match event {
             Event::Key(key) => Self::handle_key_event(viewer, key),
             Event::Mouse(mouse) => Self::handle_mouse_event(viewer, mouse),
+            Event::Paste(text) => {
+                viewer.paste(&text);
+                ViewerAction::None
+            }
             _ => ViewerAction::None,
         }
     }
     
     fn handle_key_event(viewer: &mut Viewer, key: crossterm::event::KeyEvent) -> ViewerAction {
-        if viewer.is_in_search_mode() {
-            Self::handle_search_key(viewer, key)
+        viewer.clear_status_message();
+        
+        if viewer.is_search_running() && key.code == KeyCode::Esc {
+            viewer.cancel_search();
+            ViewerAction::None
+        } else if viewer.is_formatting() && key.code == KeyCode::Esc {
+            viewer.cancel_formatting();
+            ViewerAction::None
+        } else if viewer.is_command_running() && key.code == KeyCode::Esc {
+            viewer.cancel_command();
+            ViewerAction::None
+        } else if viewer.is_in_prompt() {
+            Self::handle_prompt_key(viewer, key)
         } else if viewer.has_context_menu() {
             Self::handle_context_menu_key(viewer, key)
         } else {
@@ -22,29 +71,64 @@ impl EventHandler {
         }
     }
     
-    fn handle_search_key(viewer: &mut Viewer, key: crossterm::event::KeyEvent) -> ViewerAction {
+    fn handle_prompt_key(viewer: &mut Viewer, key: crossterm::event::KeyEvent) -> ViewerAction {
         match key.code {
             KeyCode::Esc => {
-                viewer.exit_search_mode();
+                viewer.exit_prompt();
                 ViewerAction::None
             }
             KeyCode::Enter => {
-                viewer.request_search();
-                viewer.exit_search_mode();
+                viewer.submit_prompt();
                 ViewerAction::None
             }
             _ => {
                 // Let TextArea handle all other input including backspace, typing, cursor movement, etc.
-                viewer.handle_search_input(key);
+                viewer.handle_prompt_input(key);
                 ViewerAction::None
             }
         }
     }
     
     fn handle_normal_key(viewer: &mut Viewer, key: crossterm::event::KeyEvent) -> ViewerAction {
+        // The key after `m` or `'` names the mark
+        if viewer.has_pending_mark() {
+            match key.code {
+                KeyCode::Char(name) => viewer.complete_mark(name),
+                _ => viewer.cancel_mark(),
+            }
+            return ViewerAction::None;
+        }
+        
+        // The key after `z` picks the fold command, and any count typed before `z` applies to it
+        if viewer.has_pending_fold() {
+            let count = viewer.take_pending_count();
+            match key.code {
+                KeyCode::Char(command) => viewer.complete_fold_command(command, count),
+                _ => viewer.cancel_fold_command(),
+            }
+            return ViewerAction::None;
+        }
+        if key.code == KeyCode::Char('z') {
+            viewer.begin_fold_command();
+            return ViewerAction::None;
+        }
+        
+        // Digits build up a count for the next command, vim style
+        if let KeyCode::Char(c @ '0'..='9') = key.code {
+            if c != '0' || viewer.has_pending_count() {
+                viewer.push_count_digit(c.to_digit(10).unwrap_or(0));
+                return ViewerAction::None;
+            }
+        }
+        let count = viewer.take_pending_count();
+        
         match key.code {
             KeyCode::Char('q') => ViewerAction::Quit,
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => ViewerAction::Quit,
+            KeyCode::Esc if viewer.is_in_visual_mode() => {
+                viewer.clear_selection();
+                ViewerAction::None
+            }
             KeyCode::Esc => {
                 viewer.clear_search();
                 ViewerAction::None
@@ -53,6 +137,117 @@ impl EventHandler {
                 viewer.enter_search_mode();
                 ViewerAction::None
             }
+            KeyCode::Char(':') => {
+                viewer.enter_goto_mode();
+                ViewerAction::None
+            }
+            KeyCode::Char('?') => {
+                viewer.enter_query_mode();
+                ViewerAction::None
+            }
+            KeyCode::Char('m') => {
+                viewer.begin_mark(MarkAction::Set);
+                ViewerAction::None
+            }
+            KeyCode::Char('\'') => {
+                viewer.begin_mark(MarkAction::Jump);
+                ViewerAction::None
+            }
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                viewer.jump_back();
+                ViewerAction::None
+            }
+            KeyCode::Char('i') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                viewer.jump_forward();
+                ViewerAction::None
+            }
+            KeyCode::Tab => {
+                viewer.jump_forward();
+                ViewerAction::None
+            }
+            KeyCode::Char('e') => {
+                viewer.toggle_record_expansion(count);
+                ViewerAction::None
+            }
+            KeyCode::Char('E') => {
+                viewer.collapse_all_records();
+                ViewerAction::None
+            }
+            KeyCode::Char('%') => {
+                viewer.jump_to_matching();
+                ViewerAction::None
+            }
+            KeyCode::Char('f') => {
+                viewer.toggle_formatted_view();
+                ViewerAction::None
+            }
+            KeyCode::Char('t') => {
+                viewer.toggle_table_view();
+                ViewerAction::None
+            }
+            KeyCode::Left if viewer.is_table_view() => {
+                viewer.scroll_columns(-(count.unwrap_or(1) as isize));
+                ViewerAction::None
+            }
+            KeyCode::Right if viewer.is_table_view() => {
+                viewer.scroll_columns(count.unwrap_or(1) as isize);
+                ViewerAction::None
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                viewer.move_cursor_columns(-(count.unwrap_or(1) as isize));
+                ViewerAction::None
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                viewer.move_cursor_columns(count.unwrap_or(1) as isize);
+                ViewerAction::None
+            }
+            KeyCode::Char('j') => {
+                viewer.move_cursor_lines(count.unwrap_or(1) as isize);
+                ViewerAction::None
+            }
+            KeyCode::Char('k') => {
+                viewer.move_cursor_lines(-(count.unwrap_or(1) as isize));
+                ViewerAction::None
+            }
+            KeyCode::Char('0') => {
+                viewer.move_cursor_to_line_start();
+                ViewerAction::None
+            }
+            KeyCode::Char('$') => {
+                viewer.move_cursor_to_line_end();
+                ViewerAction::None
+            }
+            KeyCode::Char('v') => {
+                viewer.toggle_visual_mode(VisualMode::Char);
+                ViewerAction::None
+            }
+            KeyCode::Char('V') => {
+                viewer.toggle_visual_mode(VisualMode::Line);
+                ViewerAction::None
+            }
+            KeyCode::Char('y') => {
+                viewer.yank_selection();
+                ViewerAction::None
+            }
+            KeyCode::Enter => {
+                viewer.open_context_menu_at_cursor();
+                ViewerAction::None
+            }
+            KeyCode::Char('x') => {
+                viewer.hide_column(count);
+                ViewerAction::None
+            }
+            KeyCode::Char('X') => {
+                viewer.show_all_columns();
+                ViewerAction::None
+            }
+            KeyCode::Char('|') => {
+                match count {
+                    Some(column) => viewer.jump_to_column(column),
+                    None => viewer.enter_column_mode(),
+                }
+                ViewerAction::None
+            }
             KeyCode::Char('n') => {
                 viewer.next_match();
                 ViewerAction::None
@@ -61,6 +256,15 @@ impl EventHandler {
                 viewer.prev_match();
                 ViewerAction::None
             }
+            // The arrows scroll, unless they are extending a visual selection
+            KeyCode::Up if viewer.is_in_visual_mode() => {
+                viewer.move_cursor_lines(-1);
+                ViewerAction::None
+            }
+            KeyCode::Down if viewer.is_in_visual_mode() => {
+                viewer.move_cursor_lines(1);
+                ViewerAction::None
+            }
             KeyCode::Up => {
                 viewer.scroll_up();
                 ViewerAction::None
@@ -86,11 +290,17 @@ impl EventHandler {
                 ViewerAction::None
             }
             KeyCode::Char('g') => {
-                viewer.goto_start();
+                match count {
+                    Some(line) => viewer.jump_to_line(line.saturating_sub(1)),
+                    None => viewer.goto_start(),
+                }
                 ViewerAction::None
             }
             KeyCode::Char('G') => {
-                viewer.goto_end();
+                match count {
+                    Some(line) => viewer.jump_to_line(line.saturating_sub(1)),
+                    None => viewer.goto_end(),
+                }
                 ViewerAction::None
             }
             _ => ViewerAction::None,
@@ -103,6 +313,18 @@ impl EventHandler {
                 viewer.close_context_menu();
                 ViewerAction::None
             }
+            KeyCode::Up | KeyCode::Char('k') => {
+                viewer.move_menu_selection(-1);
+                ViewerAction::None
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                viewer.move_menu_selection(1);
+                ViewerAction::None
+            }
+            KeyCode::Enter => {
+                viewer.run_selected_menu_action();
+                ViewerAction::None
+            }
             _ => ViewerAction::None,
         }
     }
@@ -125,6 +347,10 @@ impl EventHandler {
                 viewer.scroll_down_multiple(crate::constants::Constants::SCROLL_LINES_PER_WHEEL);
                 ViewerAction::None
             }
+            MouseEventKind::Down(MouseButton::Left) if mouse.modifiers.contains(KeyModifiers::SHIFT) => {
+                viewer.extend_selection(mouse.column, mouse.row);
+                ViewerAction::None
+            }
             MouseEventKind::Down(MouseButton::Left) => {
                 viewer.start_selection(mouse.column, mouse.row);
                 ViewerAction::None
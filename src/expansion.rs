@@ -0,0 +1,56 @@
+use crate::formatter::FileFormatter;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Records of a JSON Lines file that are shown pretty-printed in place of their line
+#[derive(Debug, Default)]
+pub struct RecordExpansions {
+    records: HashMap<usize, Vec<String>>,
+}
+
+impl RecordExpansions {
+    /// Expand a line, or collapse it if it is already expanded. Returns whether it is now expanded.
+    pub fn toggle(&mut self, line_num: usize, line: &str) -> Result<bool> {
+        if self.records.remove(&line_num).is_some() {
+            return Ok(false);
+        }
+        
+        let pretty = FileFormatter::pretty_print_json(line)?;
+        self.records.insert(line_num, pretty.lines().map(str::to_string).collect());
+        Ok(true)
+    }
+    
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+    
+    /// The pretty-printed rows shown for a line, if it is expanded
+    pub fn rows(&self, line_num: usize) -> Option<&[String]> {
+        self.records.get(&line_num).map(Vec::as_slice)
+    }
+    
+    /// Number of screen rows a line takes up
+    pub fn row_count(&self, line_num: usize) -> usize {
+        self.rows(line_num).map_or(1, |rows| rows.len().max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_toggle_record() {
+        let mut expansions = RecordExpansions::default();
+        assert!(expansions.toggle(3, r#"{"a": [1]}"#).unwrap());
+        assert_eq!(expansions.rows(3).unwrap(), ["{", "  \"a\": [", "    1", "  ]", "}"]);
+        assert_eq!(expansions.row_count(3), 5);
+        assert_eq!(expansions.row_count(4), 1);
+        
+        assert!(!expansions.toggle(3, r#"{"a": [1]}"#).unwrap());
+        assert_eq!(expansions.rows(3), None);
+        
+        assert!(expansions.toggle(5, "not json").is_err());
+        assert_eq!(expansions.rows(5), None);
+    }
+}
@@ -1,14 +1,41 @@
 use anyhow::{Context, Result};
+use crate::{constants::Constants, text_utils::TextUtils};
 use memmap2::Mmap;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 pub type ProgressCallback = Box<dyn Fn(f64, &str) + Send + Sync>;
 
+/// A single search hit, with `start` and `end` given as character offsets within the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// How a file changed on disk since it was last mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Unchanged,
+    /// Data was only added at the end, so existing lines are still where they were
+    Appended,
+    /// The content was rewritten or the file replaced, so old lines and offsets mean nothing
+    Replaced,
+}
+
+/// Memory-mapped file with a line index. Cloning is cheap and shares the mapping,
+/// so a clone can be handed to a background thread.
+#[derive(Clone)]
 pub struct FileReader {
+    path: PathBuf,
     mmap: Arc<Mmap>,
-    lines: Vec<usize>, // Line start positions
+    lines: Arc<Vec<usize>>, // Line start positions
+    /// Modification time of the mapped file, to notice rewrites that keep its size
+    modified: Option<SystemTime>,
 }
 
 impl FileReader {
@@ -25,35 +52,90 @@ impl FileReader {
         let _ = std::fs::remove_file(&temp_file);
         
         Ok(FileReader {
+            path: temp_file,
             mmap: Arc::new(empty_mmap),
-            lines: vec![0],
+            lines: Arc::new(vec![0]),
+            modified: None,
         })
     }
     
     pub fn new_with_progress<P: AsRef<Path>>(path: P, progress_callback: Option<ProgressCallback>) -> Result<Self> {
-        let file = File::open(&path)
-            .with_context(|| format!("Failed to open file: {}", path.as_ref().display()))?;
-        
-        let mmap = Arc::new(unsafe { Mmap::map(&file) }
-            .with_context(|| "Failed to memory-map file")?);
+        let (mmap, modified) = Self::map_file(path.as_ref())?;
         
         // Build line index for fast line-based navigation
         let mut lines = vec![0]; // First line starts at position 0
-        let total_bytes = mmap.len();
-        let mut last_progress_pos = 0;
+        Self::index_lines(&mmap, 0, &mut lines, progress_callback.as_ref());
+        
+        Ok(FileReader {
+            path: path.as_ref().to_path_buf(),
+            mmap: Arc::new(mmap),
+            lines: Arc::new(lines),
+            modified,
+        })
+    }
+    
+    /// Re-map the file after it changed on disk. Appended data only indexes the new
+    /// bytes; a file that shrank or was rewritten gets a fresh index. A file replaced by
+    /// one of the same size, as an editor's save or a log rotation does, is told apart by
+    /// its modification time.
+    pub fn refresh(&mut self) -> Result<FileChange> {
+        let old_len = self.mmap.len();
+        let (mmap, modified) = Self::map_file(&self.path)?;
+        let new_len = mmap.len();
+        
+        if new_len == old_len && modified == self.modified {
+            return Ok(FileChange::Unchanged);
+        }
+        
+        // Treat growth as an append when the tail of the previously indexed data is unchanged
+        let tail_start = old_len.saturating_sub(Constants::REFRESH_TAIL_CHECK_BYTES);
+        let appended = new_len > old_len && mmap[tail_start..old_len] == self.mmap[tail_start..];
+        
+        let lines = Arc::make_mut(&mut self.lines);
+        if appended {
+            Self::index_lines(&mmap, old_len, lines, None);
+        } else {
+            lines.clear();
+            lines.push(0);
+            Self::index_lines(&mmap, 0, lines, None);
+        }
+        
+        self.mmap = Arc::new(mmap);
+        self.modified = modified;
+        Ok(if appended { FileChange::Appended } else { FileChange::Replaced })
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    fn map_file(path: &Path) -> Result<(Mmap, Option<SystemTime>)> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let modified = file.metadata().and_then(|metadata| metadata.modified()).ok();
+        
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| "Failed to memory-map file")?;
+        Ok((mmap, modified))
+    }
+    
+    /// Append the start of every line that begins after `from` to `lines`
+    fn index_lines(bytes: &[u8], from: usize, lines: &mut Vec<usize>, progress_callback: Option<&ProgressCallback>) {
+        let total_bytes = bytes.len();
+        let mut last_progress_pos = from;
         let progress_interval = total_bytes / 20; // Update every 5%
         
-        if let Some(ref callback) = progress_callback {
+        if let Some(callback) = progress_callback {
             callback(0.0, "Indexing file...");
         }
         
-        for (pos, &byte) in mmap.iter().enumerate() {
+        for (pos, &byte) in bytes.iter().enumerate().skip(from) {
             if byte == b'\n' {
                 lines.push(pos + 1);
             }
             
             // Report progress every 5% to reduce overhead
-            if let Some(ref callback) = progress_callback {
+            if let Some(callback) = progress_callback {
                 if pos > last_progress_pos + progress_interval {
                     let progress = pos as f64 / total_bytes as f64;
                     callback(progress, "Indexing file...");
@@ -62,17 +144,25 @@ impl FileReader {
             }
         }
         
-        if let Some(ref callback) = progress_callback {
+        if let Some(callback) = progress_callback {
             callback(1.0, "File indexing complete");
         }
-        
-        Ok(FileReader { mmap, lines })
     }
     
     pub fn line_count(&self) -> usize {
         self.lines.len()
     }
     
+    /// The line containing the given byte offset. Offsets past the end map to the last line.
+    pub fn line_for_offset(&self, offset: usize) -> usize {
+        self.lines.partition_point(|&start| start <= offset).saturating_sub(1)
+    }
+    
+    /// Byte offset where a line starts. Lines past the end map to the start of the last line.
+    pub fn line_start(&self, line_num: usize) -> usize {
+        self.lines[line_num.min(self.lines.len() - 1)]
+    }
+    
     pub fn get_line(&self, line_num: usize) -> Option<&str> {
         if line_num >= self.lines.len() {
             return None;
@@ -93,19 +183,9 @@ impl FileReader {
         std::str::from_utf8(line_bytes).ok()
     }
     
-    pub fn get_lines(&self, start: usize, count: usize) -> Vec<String> {
-        let mut result = Vec::new();
-        for i in start..start + count {
-            if let Some(line) = self.get_line(i) {
-                result.push(line.to_string());
-            } else {
-                break;
-            }
-        }
-        result
-    }
-    
-    pub fn search_with_progress(&self, needle: &str, progress_callback: Option<ProgressCallback>) -> Vec<usize> {
+    /// Search every line for `needle`. Stops early once `cancel` is set, in which case
+    /// the returned matches are incomplete and should be discarded.
+    pub fn search_with_progress(&self, needle: &str, progress_callback: Option<ProgressCallback>, cancel: &AtomicBool) -> Vec<SearchMatch> {
         let mut matches = Vec::new();
         let total_lines = self.lines.len();
         let mut last_progress_line = 0;
@@ -115,10 +195,14 @@ impl FileReader {
             callback(0.0, "Searching...");
         }
         
-        for (line_num, _) in self.lines.iter().enumerate() {
+        for line_num in 0..total_lines {
+            if cancel.load(Ordering::Relaxed) {
+                return matches;
+            }
+            
             if let Some(line) = self.get_line(line_num) {
-                if line.contains(needle) {
-                    matches.push(line_num);
+                for (start, end) in TextUtils::find_char_ranges(line, needle) {
+                    matches.push(SearchMatch { line: line_num, start, end });
                 }
             }
             
@@ -138,75 +222,38 @@ impl FileReader {
         
         matches
     }
-    
-    /// Create a search context that can be safely sent to another thread
-    pub fn create_search_context(&self) -> SearchContext {
-        SearchContext {
-            mmap: self.mmap.clone(),
-            lines: self.lines.clone(),
-        }
-    }
-    
 }
 
-/// A thread-safe context for searching that can be sent between threads
-pub struct SearchContext {
-    mmap: Arc<Mmap>,
-    lines: Vec<usize>,
+/// A file in the temp directory holding some text, removed again when dropped, for tests
+/// that need a `FileReader`
+#[cfg(test)]
+pub struct TempFile {
+    path: PathBuf,
 }
 
-impl SearchContext {
-    pub fn search_with_progress(&self, needle: &str, progress_callback: Option<ProgressCallback>) -> Vec<usize> {
-        let mut matches = Vec::new();
-        let total_lines = self.lines.len();
-        let mut last_progress_line = 0;
-        let progress_interval = total_lines / 20; // Update every 5%
-        
-        if let Some(ref callback) = progress_callback {
-            callback(0.0, "Searching...");
-        }
-        
-        for (line_num, _) in self.lines.iter().enumerate() {
-            if let Some(line) = self.get_line(line_num) {
-                if line.contains(needle) {
-                    matches.push(line_num);
-                }
-            }
-            
-            // Report progress every 5% to reduce overhead
-            if let Some(ref callback) = progress_callback {
-                if line_num > last_progress_line + progress_interval {
-                    let progress = line_num as f64 / total_lines as f64;
-                    callback(progress, "Searching...");
-                    last_progress_line = line_num;
-                }
-            }
-        }
-        
-        if let Some(ref callback) = progress_callback {
-            callback(1.0, "Search complete");
-        }
-        
-        matches
+#[cfg(test)]
+impl TempFile {
+    pub fn new(text: &str) -> Self {
+        // Tests run in parallel within the process, so the pid alone isn't unique
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("bigview_test_{}_{}", std::process::id(), id));
+        std::fs::write(&path, text).unwrap();
+        Self { path }
     }
     
-    fn get_line(&self, line_num: usize) -> Option<&str> {
-        if line_num >= self.lines.len() {
-            return None;
-        }
-        
-        let start = self.lines[line_num];
-        let end = if line_num + 1 < self.lines.len() {
-            self.lines[line_num + 1].saturating_sub(1) // Exclude newline
-        } else {
-            self.mmap.len()
-        };
-        
-        if start > end || start >= self.mmap.len() {
-            return None;
-        }
-        
-        let line_bytes = &self.mmap[start..end];
-        std::str::from_utf8(line_bytes).ok()
+    pub fn reader(&self) -> FileReader {
+        FileReader::new_with_progress(&self.path, None).unwrap()
     }
-}
\ No newline at end of file
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
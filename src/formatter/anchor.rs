@@ -0,0 +1,58 @@
+use crate::constants::Constants;
+
+/// Finds where the lines of re-serialized output came from, for formatters that parse a
+/// whole document and print it again rather than streaming tokens through.
+///
+/// Keys keep their order through a round trip, so each key is looked for a short way ahead
+/// of the previous one. Lines without a key, or whose key is not found nearby, share the
+/// offset of the line before, which keeps the line map in order.
+pub struct SourceAnchors<'a> {
+    source: &'a str,
+    /// Where the next search starts, just past the last key found
+    search_from: usize,
+    last_offset: usize,
+}
+
+impl<'a> SourceAnchors<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self { source, search_from: 0, last_offset: 0 }
+    }
+    
+    /// Source offset for an output line starting with `token`, or continuing the previous line for `None`
+    pub fn locate(&mut self, token: Option<&str>) -> u64 {
+        if let Some(token) = token.filter(|token| !token.is_empty()) {
+            let mut window_end = (self.search_from + Constants::FORMAT_ANCHOR_SEARCH_BYTES).min(self.source.len());
+            while !self.source.is_char_boundary(window_end) {
+                window_end -= 1;
+            }
+            
+            if let Some(found) = self.source[self.search_from..window_end].find(token) {
+                self.last_offset = self.search_from + found;
+                self.search_from = self.last_offset + token.len();
+            }
+        }
+        self.last_offset as u64
+    }
+    
+    /// How far through the source the anchors have got
+    pub fn position(&self) -> usize {
+        self.search_from
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_locate() {
+        let source = "b: 1\na:\n  b: 2\n";
+        let mut anchors = SourceAnchors::new(source);
+        assert_eq!(anchors.locate(Some("b")), 0);
+        assert_eq!(anchors.locate(Some("a")), 5);
+        assert_eq!(anchors.locate(None), 5);
+        assert_eq!(anchors.locate(Some("b")), 10);
+        // A key that can't be found keeps the map where it was
+        assert_eq!(anchors.locate(Some("missing")), 10);
+    }
+}
@@ -0,0 +1,217 @@
+use super::SyntaxError;
+use crate::{constants::Constants, file_reader::ProgressCallback};
+use anyhow::{bail, Context, Result};
+use memmap2::Mmap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Bump whenever formatter output changes, so stale cache entries are not reused
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// Managed directory of formatted copies, keyed by a hash of the original content.
+///
+/// Each entry is a formatted file plus its line map. Both are written under temporary
+/// names and renamed into place once complete, so an interrupted run never leaves a
+/// truncated entry behind. The copy of a malformed file only runs up to its first syntax
+/// error, which is kept in a note beside the entry. Entries that have not been used for a while are removed.
+pub struct FormatCache {
+    dir: PathBuf,
+}
+
+impl FormatCache {
+    /// Open the cache directory, creating it if necessary. `BIGVIEW_CACHE_DIR` overrides
+    /// the default location under the user's cache directory.
+    pub fn open() -> Result<Self> {
+        let dir = Self::default_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create cache directory: {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+    
+    fn default_dir() -> PathBuf {
+        if let Some(dir) = std::env::var_os("BIGVIEW_CACHE_DIR") {
+            return PathBuf::from(dir);
+        }
+        
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        base.join("bigview")
+    }
+    
+    /// Path of the cache entry for `input` formatted as `extension`
+    pub fn entry_path(&self, input: &Path, extension: &str, progress_callback: Option<&ProgressCallback>, cancel: &AtomicBool) -> Result<PathBuf> {
+        let hash = Self::content_hash(input, progress_callback, cancel)?;
+        Ok(self.dir.join(format!("{:016x}.{}", hash, extension)))
+    }
+    
+    /// Path of the line map stored alongside a formatted file
+    pub fn line_map_path(entry: &Path) -> PathBuf {
+        Self::with_suffix(entry, ".map")
+    }
+    
+    /// Path of the note holding the syntax error a partial entry stops at
+    fn error_path(entry: &Path) -> PathBuf {
+        Self::with_suffix(entry, ".error")
+    }
+    
+    /// Whether a complete entry exists. A hit refreshes its timestamps so pruning keeps it.
+    pub fn lookup(&self, entry: &Path) -> bool {
+        let found = [entry.to_path_buf(), Self::line_map_path(entry)].iter().all(|path| Self::touch(path));
+        if found {
+            Self::touch(&Self::error_path(entry));
+        }
+        found
+    }
+    
+    fn touch(path: &Path) -> bool {
+        match File::options().append(true).open(path) {
+            Ok(file) => {
+                let _ = file.set_modified(SystemTime::now());
+                true
+            }
+            Err(_) => false,
+        }
+    }
+    
+    /// The syntax error an entry stops at, if it was formatted from malformed input
+    pub fn load_error(entry: &Path) -> Option<SyntaxError> {
+        let note = fs::read_to_string(Self::error_path(entry)).ok()?;
+        let (offset, message) = note.split_once('\n')?;
+        Some(SyntaxError { offset: offset.parse().ok()?, message: message.to_string() })
+    }
+    
+    /// Record the syntax error an entry stops at, or clear a stale note when there is none
+    pub fn save_error(entry: &Path, error: Option<&SyntaxError>) -> Result<()> {
+        let path = Self::error_path(entry);
+        match error {
+            Some(error) => fs::write(&path, format!("{}\n{}", error.offset, error.message))
+                .with_context(|| format!("Failed to store format error: {}", path.display())),
+            None => {
+                let _ = fs::remove_file(&path);
+                Ok(())
+            }
+        }
+    }
+    
+    /// Let `write` fill temporary files for the output and its line map, then move both into
+    /// place. The temporary names are unique to the process and task.
+    pub fn store<T>(output: &Path, line_map: &Path, task_id: u64, write: impl FnOnce(&Path, &Path) -> Result<T>) -> Result<T> {
+        let suffix = format!(".tmp-{}-{}", std::process::id(), task_id);
+        let temp_output = Self::with_suffix(output, &suffix);
+        let temp_line_map = Self::with_suffix(line_map, &suffix);
+        
+        let result = write(&temp_output, &temp_line_map)
+            .and_then(|value| {
+                fs::rename(&temp_line_map, line_map)
+                    .with_context(|| format!("Failed to store line map: {}", line_map.display()))?;
+                Ok(value)
+            })
+            .and_then(|value| {
+                fs::rename(&temp_output, output)
+                    .with_context(|| format!("Failed to store formatted file: {}", output.display()))?;
+                Ok(value)
+            });
+        
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_output);
+            let _ = fs::remove_file(&temp_line_map);
+        }
+        result
+    }
+    
+    fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+    
+    /// Whether a file name is one the cache creates: `{hash}.{ext}`, optionally followed by
+    /// `.map` or `.error`, and by a `.tmp-*` suffix while it is being written
+    fn is_cache_file(name: &str) -> bool {
+        let Some((hash, rest)) = name.split_at_checked(16) else {
+            return false;
+        };
+        let Some(rest) = rest.strip_prefix('.') else {
+            return false;
+        };
+        let rest = rest.split_once(".tmp-").map_or(rest, |(entry, _)| entry);
+        let (extension, kind) = rest.split_once('.').unwrap_or((rest, ""));
+        
+        hash.chars().all(|c| c.is_ascii_hexdigit())
+            && !extension.is_empty()
+            && extension.chars().all(|c| c.is_ascii_alphanumeric())
+            && ["", "map", "error"].contains(&kind)
+    }
+    
+    /// Remove entries, and leftovers from interrupted runs, that have not been used recently.
+    /// Other files are left alone, since `BIGVIEW_CACHE_DIR` may point anywhere.
+    pub fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        
+        let max_age = Duration::from_secs(Constants::FORMAT_CACHE_MAX_AGE_DAYS * 24 * 60 * 60);
+        for entry in entries.flatten() {
+            let expired = entry.metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > max_age);
+            
+            if expired && entry.file_name().to_str().is_some_and(Self::is_cache_file) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+    
+    fn content_hash(input: &Path, progress_callback: Option<&ProgressCallback>, cancel: &AtomicBool) -> Result<u64> {
+        let file = File::open(input)
+            .with_context(|| format!("Failed to read file: {}", input.display()))?;
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| "Failed to memory-map file")?;
+        
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u32(CACHE_FORMAT_VERSION);
+        hasher.write_usize(mmap.len());
+        
+        let chunk_size = Constants::FORMAT_CACHE_HASH_CHUNK;
+        let chunk_count = mmap.len().div_ceil(chunk_size).max(1);
+        let progress_interval = (chunk_count / 20).max(1); // Update every 5%
+        
+        for (index, chunk) in mmap.chunks(chunk_size).enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                bail!("Formatting cancelled");
+            }
+            hasher.write(chunk);
+            
+            if let Some(callback) = progress_callback {
+                if index % progress_interval == 0 {
+                    callback(index as f64 / chunk_count as f64, "Checking format cache...");
+                }
+            }
+        }
+        
+        Ok(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_is_cache_file() {
+        for name in ["0123456789abcdef.json", "0123456789abcdef.json.map", "0123456789abcdef.xml.error", "0123456789abcdef.js.map.tmp-42-3"] {
+            assert!(FormatCache::is_cache_file(name), "{}", name);
+        }
+        for name in ["notes.txt", "0123456789abcdef", "0123456789abcdeg.json", "0123456789abcdef.json.bak", "0123456789abcdef..map", "report-2024-01-01.json"] {
+            assert!(!FormatCache::is_cache_file(name), "{}", name);
+        }
+    }
+}
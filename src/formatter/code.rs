@@ -0,0 +1,476 @@
+use super::sink::FormatSink;
+use crate::file_reader::ProgressCallback;
+use anyhow::Result;
+use std::io::Write;
+use std::ops::Range;
+
+const INDENT: &[u8] = b"    ";
+
+/// Keywords after which a `/` starts a regular expression rather than a division
+const REGEX_KEYWORDS: &[&[u8]] = &[
+    b"return", b"typeof", b"instanceof", b"in", b"of", b"new", b"delete", b"void", b"throw", b"case", b"do", b"else",
+    b"yield", b"await",
+];
+
+/// Keywords that stay on the line of the `}` before them, as in `} else {`
+const BLOCK_CONTINUATIONS: &[&[u8]] = &[b"else", b"catch", b"finally", b"while"];
+
+/// Languages the code re-indenter understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLanguage {
+    JavaScript,
+    Css,
+}
+
+/// Token-based re-indenter for JavaScript and CSS, mostly for reading minified bundles.
+///
+/// Nothing is parsed beyond tokens: lines are broken after `{`, `;` and `}`, and indented by
+/// how many braces are open. Tokens are copied verbatim, strings, comments and regular
+/// expressions included, and the spacing between tokens on a line is kept, with runs of
+/// whitespace collapsed to one space. Line breaks in the source are kept too, so that code
+/// relying on automatic semicolons still has one statement per line. Malformed input is
+/// re-indented as far as it goes, there are no syntax errors.
+pub struct CodeFormatter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    /// Identifiers, keywords and numbers
+    Word,
+    /// String, template and regular expression literals
+    Literal,
+    LineComment,
+    BlockComment,
+    Open(u8),
+    Close(u8),
+    Semicolon,
+    Comma,
+    /// Any other single byte
+    Operator,
+}
+
+/// What goes between the previous token and the next one, in increasing order of precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Break {
+    None,
+    Space,
+    /// A line break after a `}`, unless the next token continues the statement
+    AfterBlock,
+    Newline,
+    BlankLine,
+}
+
+/// An open bracket, and for braces whether they hold an object literal rather than a block
+#[derive(Debug, Clone, Copy)]
+struct Bracket {
+    byte: u8,
+    object: bool,
+}
+
+struct CodeStream<'a, 'b, 'c> {
+    input: &'a [u8],
+    output: &'b mut FormatSink<'c>,
+    language: CodeLanguage,
+    /// Input offset of `input[0]`, when it is part of a larger file
+    base_offset: u64,
+    base_depth: usize,
+    pos: usize,
+    /// Brackets of every kind that are still open
+    brackets: Vec<Bracket>,
+    pending: Break,
+    /// The last token other than a comment, and the input it was read from
+    previous: Option<(Token, Range<usize>)>,
+    /// The last token of any kind
+    last: Option<Token>,
+}
+
+impl CodeFormatter {
+    pub fn format(input: &[u8], language: CodeLanguage, output: &mut FormatSink, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        CodeStream::new(input, language, output, 0, 0).run(progress_callback)
+    }
+    
+    /// Re-indent code embedded in another file, such as a `<script>` element, at input offset
+    /// `offset` and nested `depth` levels deep. The first token is written where the output
+    /// currently is.
+    pub fn format_embedded(input: &[u8], offset: u64, depth: usize, language: CodeLanguage, output: &mut FormatSink) -> Result<()> {
+        CodeStream::new(input, language, output, offset, depth).run(None)
+    }
+}
+
+impl<'a, 'b, 'c> CodeStream<'a, 'b, 'c> {
+    fn new(input: &'a [u8], language: CodeLanguage, output: &'b mut FormatSink<'c>, base_offset: u64, base_depth: usize) -> Self {
+        Self {
+            input,
+            output,
+            language,
+            base_offset,
+            base_depth,
+            pos: 0,
+            brackets: Vec::new(),
+            pending: Break::None,
+            previous: None,
+            last: None,
+        }
+    }
+    
+    fn run(mut self, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        let total_bytes = self.input.len();
+        let progress_interval = (total_bytes / 20).max(1); // Update every 5%
+        let mut last_progress_pos = 0;
+        
+        loop {
+            self.skip_whitespace();
+            if self.pos >= self.input.len() {
+                break;
+            }
+            
+            let start = self.pos;
+            let token = self.next_token();
+            self.emit(token, start)?;
+            
+            if let Some(callback) = progress_callback {
+                if start > last_progress_pos + progress_interval {
+                    let name = match self.language {
+                        CodeLanguage::JavaScript => "Formatting JavaScript...",
+                        CodeLanguage::Css => "Formatting CSS...",
+                    };
+                    callback(start as f64 / total_bytes as f64, name);
+                    last_progress_pos = start;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Line breaks only count between statements and rules, not inside brackets
+    fn at_statement_level(&self) -> bool {
+        !matches!(self.brackets.last(), Some(bracket) if bracket.byte != b'{')
+    }
+    
+    fn depth(&self) -> usize {
+        self.base_depth + self.brackets.iter().filter(|bracket| bracket.byte == b'{').count()
+    }
+    
+    fn skip_whitespace(&mut self) {
+        let start = self.pos;
+        while self.pos < self.input.len() && self.input[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if self.pos == start || self.last.is_none() {
+            return;
+        }
+        
+        let newlines = self.input[start..self.pos].iter().filter(|&&byte| byte == b'\n').count();
+        let kept = match newlines {
+            0 => Break::Space,
+            _ if !self.at_statement_level() => Break::Space,
+            1 => Break::Newline,
+            _ => Break::BlankLine,
+        };
+        self.pending = self.pending.max(kept);
+    }
+    
+    fn next_token(&mut self) -> Token {
+        let input = self.input;
+        let byte = input[self.pos];
+        let next = input.get(self.pos + 1).copied();
+        let javascript = self.language == CodeLanguage::JavaScript;
+        
+        match byte {
+            b'/' if next == Some(b'*') => {
+                self.pos = find(input, self.pos + 2, b"*/").map_or(input.len(), |end| end + 2);
+                Token::BlockComment
+            }
+            b'/' if javascript && next == Some(b'/') => {
+                self.pos = find(input, self.pos, b"\n").unwrap_or(input.len());
+                Token::LineComment
+            }
+            b'/' if javascript && self.regex_allowed() => match Self::scan_regex(input, self.pos + 1) {
+                Some(end) => {
+                    self.pos = end;
+                    Token::Literal
+                }
+                None => {
+                    self.pos += 1;
+                    Token::Operator
+                }
+            },
+            b'"' | b'\'' => {
+                self.pos = Self::scan_string(input, self.pos);
+                Token::Literal
+            }
+            b'`' if javascript => {
+                self.pos = Self::scan_template(input, self.pos + 1);
+                Token::Literal
+            }
+            b'{' | b'(' | b'[' => {
+                self.pos += 1;
+                Token::Open(byte)
+            }
+            b'}' | b')' | b']' => {
+                self.pos += 1;
+                Token::Close(byte)
+            }
+            b';' => {
+                self.pos += 1;
+                Token::Semicolon
+            }
+            b',' => {
+                self.pos += 1;
+                Token::Comma
+            }
+            _ if Self::is_word_byte(byte) => {
+                while self.pos < input.len() && Self::is_word_byte(input[self.pos]) {
+                    self.pos += 1;
+                }
+                Token::Word
+            }
+            _ => {
+                self.pos += 1;
+                Token::Operator
+            }
+        }
+    }
+    
+    fn emit(&mut self, token: Token, start: usize) -> Result<()> {
+        match token {
+            // An empty block stays as `{}`
+            Token::Close(b'}') if self.last == Some(Token::Open(b'{')) => self.pending = Break::None,
+            Token::Close(b'}') => self.pending = self.pending.max(Break::Newline),
+            _ => {}
+        }
+        if let Token::Close(bracket) = token {
+            let opening = match bracket {
+                b'}' => b'{',
+                b')' => b'(',
+                _ => b'[',
+            };
+            // Unbalanced brackets are tolerated, the innermost one of the same kind is closed
+            if let Some(index) = self.brackets.iter().rposition(|open| open.byte == opening) {
+                self.brackets.truncate(index);
+            }
+        }
+        
+        let offset = self.base_offset + start as u64;
+        let raw = &self.input[start..self.pos];
+        let pending = match self.pending {
+            Break::AfterBlock if self.continues_block(token, raw) => match token {
+                Token::Word => Break::Space,
+                _ => Break::None,
+            },
+            Break::AfterBlock => Break::Newline,
+            pending => pending,
+        };
+        match pending {
+            Break::None => {}
+            Break::Space => self.output.write_all(b" ")?,
+            Break::Newline | Break::BlankLine | Break::AfterBlock => {
+                if pending == Break::BlankLine {
+                    self.output.write_all(b"\n")?;
+                }
+                self.output.start_line(self.depth(), INDENT, offset)?;
+            }
+        }
+        self.output.write_verbatim(raw, offset)?;
+        
+        self.pending = match token {
+            Token::Open(byte) => {
+                let object = byte == b'{' && self.opens_object();
+                self.brackets.push(Bracket { byte, object });
+                if byte == b'{' { Break::Newline } else { Break::None }
+            }
+            Token::Close(b'}') => Break::AfterBlock,
+            Token::Semicolon if self.at_statement_level() => Break::Newline,
+            // Members of object literals go one per line
+            Token::Comma if self.brackets.last().is_some_and(|bracket| bracket.object) => Break::Newline,
+            Token::LineComment => Break::Newline,
+            _ => Break::None,
+        };
+        
+        self.last = Some(token);
+        if !matches!(token, Token::LineComment | Token::BlockComment) {
+            self.previous = Some((token, start..self.pos));
+        }
+        Ok(())
+    }
+    
+    /// Whether a token carries on the statement a `}` ended, as in `})` or `} else`
+    fn continues_block(&self, token: Token, raw: &[u8]) -> bool {
+        if self.language == CodeLanguage::Css {
+            return false;
+        }
+        match token {
+            // Calls and indexing, as in `}()` at the end of an immediately invoked function
+            Token::Close(_) | Token::Open(b'(' | b'[') | Token::Semicolon | Token::Comma => true,
+            Token::Operator => matches!(raw, b"." | b"?" | b":"),
+            Token::Word => BLOCK_CONTINUATIONS.contains(&raw),
+            _ => false,
+        }
+    }
+    
+    /// Whether a `{` starts an object literal, judging by the token before it
+    fn opens_object(&self) -> bool {
+        if self.language == CodeLanguage::Css {
+            return false;
+        }
+        match self.previous {
+            Some((Token::Open(b'(' | b'[') | Token::Comma, _)) => true,
+            // `=>` is followed by a function body
+            Some((Token::Operator, ref operator)) => &self.input[operator.clone()] != b">",
+            Some((Token::Word, ref word)) => &self.input[word.clone()] == b"return",
+            _ => false,
+        }
+    }
+    
+    /// A `/` starts a regular expression where a value is expected, and is a division after one
+    fn regex_allowed(&self) -> bool {
+        match self.previous {
+            None => true,
+            Some((Token::Word, ref word)) => REGEX_KEYWORDS.contains(&&self.input[word.clone()]),
+            Some((Token::Literal | Token::Close(b')' | b']'), _)) => false,
+            Some(_) => true,
+        }
+    }
+    
+    fn is_word_byte(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'$') || byte >= 0x80
+    }
+    
+    /// The end of a quoted string starting at `pos`. An unterminated string ends with its line.
+    fn scan_string(input: &[u8], pos: usize) -> usize {
+        let quote = input[pos];
+        let mut pos = pos + 1;
+        while pos < input.len() {
+            match input[pos] {
+                b'\\' => pos += 2,
+                b'\n' => return pos,
+                byte if byte == quote => return pos + 1,
+                _ => pos += 1,
+            }
+        }
+        input.len()
+    }
+    
+    /// The end of a template literal whose opening backtick is just before `pos`
+    fn scan_template(input: &[u8], mut pos: usize) -> usize {
+        while pos < input.len() {
+            match input[pos] {
+                b'\\' => pos += 2,
+                b'`' => return pos + 1,
+                b'$' if input.get(pos + 1) == Some(&b'{') => pos = Self::scan_substitution(input, pos + 2),
+                _ => pos += 1,
+            }
+        }
+        input.len()
+    }
+    
+    /// The end of a `${...}` substitution in a template, which may hold templates of its own
+    fn scan_substitution(input: &[u8], mut pos: usize) -> usize {
+        let mut depth = 1;
+        while pos < input.len() {
+            match input[pos] {
+                b'`' => pos = Self::scan_template(input, pos + 1),
+                b'"' | b'\'' => pos = Self::scan_string(input, pos),
+                b'{' => {
+                    depth += 1;
+                    pos += 1;
+                }
+                b'}' => {
+                    depth -= 1;
+                    pos += 1;
+                    if depth == 0 {
+                        return pos;
+                    }
+                }
+                _ => pos += 1,
+            }
+        }
+        input.len()
+    }
+    
+    /// The end of a regular expression literal whose opening `/` is just before `pos`, flags
+    /// included, or `None` if the line ends first
+    fn scan_regex(input: &[u8], mut pos: usize) -> Option<usize> {
+        let mut in_class = false;
+        while pos < input.len() {
+            match input[pos] {
+                b'\\' => pos += 1,
+                b'\n' => return None,
+                b'[' => in_class = true,
+                b']' => in_class = false,
+                b'/' if !in_class => {
+                    pos += 1;
+                    while pos < input.len() && input[pos].is_ascii_alphabetic() {
+                        pos += 1;
+                    }
+                    return Some(pos);
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        None
+    }
+}
+
+/// Where `needle` next occurs in `input` at or after `from`
+pub(super) fn find(input: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    input.get(from..)?.windows(needle.len()).position(|window| window == needle).map(|position| from + position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn format(input: &str, language: CodeLanguage) -> String {
+        let mut output = Vec::new();
+        let mut sink = FormatSink::new(&mut output, std::io::sink()).unwrap();
+        CodeFormatter::format(input.as_bytes(), language, &mut sink, None).unwrap();
+        sink.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+    
+    #[test]
+    fn test_reindents_minified_javascript() {
+        let input = "(function(){var a={x:1,y:\"};{\"};if(a){f(a,function(){})}else{g()}for(var i=0;i<2;i++){h(/[}{]\\//g,`${a}}`)}})();";
+        let expected = "\
+(function(){
+    var a={
+        x:1,
+        y:\"};{\"
+    };
+    if(a){
+        f(a,function(){})
+    } else{
+        g()
+    }
+    for(var i=0;i<2;i++){
+        h(/[}{]\\//g,`${a}}`)
+    }
+})();";
+        assert_eq!(format(input, CodeLanguage::JavaScript), expected);
+        
+        // Only the commas of object literals break lines
+        let input = "!function(){return a(),{b:1,c:2}}();";
+        assert_eq!(format(input, CodeLanguage::JavaScript), "!function(){\n    return a(),{\n        b:1,\n        c:2\n    }\n}();");
+    }
+    
+    #[test]
+    fn test_keeps_line_breaks_and_comments() {
+        let input = "let a = 1\nlet b = a / 2 // half\n\n\n/* done */ f()";
+        assert_eq!(format(input, CodeLanguage::JavaScript), "let a = 1\nlet b = a / 2 // half\n\n/* done */ f()");
+    }
+    
+    #[test]
+    fn test_reindents_css() {
+        let input = "@media (max-width:600px){a,b:hover{color:red;background:url(//x.png)}}/* end */.c{}";
+        let expected = "\
+@media (max-width:600px){
+    a,b:hover{
+        color:red;
+        background:url(//x.png)
+    }
+}
+/* end */.c{}";
+        assert_eq!(format(input, CodeLanguage::Css), expected);
+    }
+}
@@ -0,0 +1,344 @@
+use super::json::JsonFormatter;
+use super::{FileFormatter, FormatKind};
+use crate::constants::Constants;
+use crate::table::TableView;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// Openings that only JavaScript has, such as the wrapper of a minified bundle
+const JS_STARTS: &[&str] = &[
+    "(function", "(()", "(async", "!function", "\"use strict\"", "'use strict'", "(self", "(window", "(globalThis",
+    "define(", "webpackJsonp",
+];
+/// Statements that start JavaScript but also other languages
+const JS_STATEMENT_STARTS: &[&str] = &[
+    "var ", "let ", "const ", "function", "async ", "import ", "import{", "export ", "class ",
+];
+const CSS_STARTS: &[&str] = &[
+    "@charset", "@import", "@media", "@font-face", "@keyframes", "@supports", "@layer", "@namespace", "@page", ":root",
+];
+/// How many lines must agree on their field count for a file to count as CSV or TSV
+const DELIMITED_SNIFF_LINES: usize = 5;
+/// How far into a file without a `---` marker to look for a nested YAML mapping
+const YAML_SNIFF_LINES: usize = 20;
+
+/// Recognises supported formats from the start of a file's content, whatever it is called
+pub struct FormatDetector;
+
+impl FormatDetector {
+    /// Sniff the first few KB of a file
+    pub fn detect(path: &Path) -> Result<Option<FormatKind>> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let mut head = Vec::with_capacity(Constants::FORMAT_SNIFF_BYTES);
+        file.take(Constants::FORMAT_SNIFF_BYTES as u64).read_to_end(&mut head)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Ok(Self::sniff(&head))
+    }
+    
+    pub fn sniff(head: &[u8]) -> Option<FormatKind> {
+        let head = head.strip_prefix(UTF8_BOM).unwrap_or(head);
+        let start = head.iter().position(|byte| !byte.is_ascii_whitespace())?;
+        
+        match (head[start], head.get(start + 1)) {
+            (b'[', _) if Self::content_lines(&head[start..]).next().is_some_and(Self::is_toml_header) => Some(FormatKind::Toml),
+            (b'{' | b'[', _) if Self::is_json_lines(&head[start..]) => Some(FormatKind::JsonLines),
+            (b'{' | b'[', _) if JsonFormatter::is_json_start(&head[start..]) => Some(FormatKind::Json),
+            (b'<', _) if Self::is_html(&head[start..]) => Some(FormatKind::Html),
+            // A declaration, comment, doctype or element name must follow the `<`
+            (b'<', Some(&next)) if next == b'?' || next == b'!' || next == b'_' || next == b':' || next.is_ascii_alphabetic() => {
+                Some(FormatKind::Xml)
+            }
+            _ => Self::sniff_key_value(&head[start..])
+                .or_else(|| Self::sniff_code(&head[start..]))
+                .or_else(|| Self::sniff_delimited(&head[start..])),
+        }
+    }
+    
+    /// YAML and TOML have no telling first byte, so look at the shape of the first two lines
+    fn sniff_key_value(head: &[u8]) -> Option<FormatKind> {
+        let mut lines = Self::content_lines(head);
+        let first = lines.next()?;
+        if first == "---" || first.starts_with("%YAML") {
+            return Some(FormatKind::Yaml);
+        }
+        if Self::is_toml_header(first) {
+            return Some(FormatKind::Toml);
+        }
+        
+        // A single matching line is too weak a hint, since prose often looks like `Note: ...`
+        let second = lines.next()?;
+        if Self::is_toml_key_value(first) && Self::is_toml_key_value(second) {
+            Some(FormatKind::Toml)
+        } else if Self::is_yaml_entry(first) && Self::is_yaml_entry(second) && Self::has_yaml_nesting(head) {
+            Some(FormatKind::Yaml)
+        } else {
+            None
+        }
+    }
+    
+    /// JavaScript and CSS are told apart by how they start, once any leading comments such as
+    /// a licence banner are skipped
+    fn sniff_code(head: &[u8]) -> Option<FormatKind> {
+        let text = String::from_utf8_lossy(head);
+        let mut rest = text.trim_start();
+        loop {
+            if let Some(comment) = rest.strip_prefix("/*") {
+                rest = comment.split_once("*/")?.1.trim_start();
+            } else if let Some(comment) = rest.strip_prefix("//") {
+                rest = comment.split_once('\n').map_or("", |(_, rest)| rest).trim_start();
+            } else {
+                break;
+            }
+        }
+        
+        // Keywords shared with other languages only count on a line that looks like JavaScript
+        let first_line = rest.lines().next().unwrap_or("");
+        if JS_STARTS.iter().any(|start| rest.starts_with(start))
+            || (JS_STATEMENT_STARTS.iter().any(|start| rest.starts_with(start)) && first_line.contains([';', '{']))
+        {
+            Some(FormatKind::JavaScript)
+        } else if CSS_STARTS.iter().any(|start| rest.starts_with(start)) || Self::is_css_rule(rest) {
+            Some(FormatKind::Css)
+        } else {
+            None
+        }
+    }
+    
+    /// A selector followed by a block that is empty or starts with a declaration, as in
+    /// `a.b:hover{color:red}`. Only the first rule is looked at, with its selector on one line
+    /// or on several that each end a selector in a list.
+    fn is_css_rule(text: &str) -> bool {
+        let Some((selector, block)) = text.split_once('{') else {
+            return false;
+        };
+        let mut selector_lines = selector.lines().map(str::trim).filter(|line| !line.is_empty()).peekable();
+        let mut one_statement = true;
+        while let Some(line) = selector_lines.next() {
+            one_statement &= selector_lines.peek().is_none() || line.ends_with(',');
+        }
+        let selector = selector.trim();
+        // Type and class names can't start with a digit, unlike the dates and times of a log
+        let plausible_selector = !selector.is_empty()
+            && one_statement
+            && !selector.ends_with(':')
+            && !selector.contains(';')
+            && (!selector.contains('=') || selector.contains('['))
+            && !selector.split([' ', '\t', '\n', ',', '>', '+', '~']).any(|part| part.starts_with(|c: char| c.is_ascii_digit()));
+        
+        // A declaration's value runs to `;` or `}`, with no other property in it
+        let block = block.trim_start();
+        let declaration = block.split_once(':').is_some_and(|(property, value)| {
+            let property = property.trim();
+            let value = value.split([';', '}']).next().unwrap_or("");
+            !property.is_empty() && property.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') && !value.contains(':')
+        });
+        plausible_selector && (block.starts_with('}') || declaration)
+    }
+    
+    /// A doctype, or a first element that only a page has, in any case
+    fn is_html(head: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(head).to_ascii_lowercase();
+        if text.starts_with("<!doctype html") {
+            return true;
+        }
+        
+        // Skip the comments and declarations before the first element
+        let mut rest = text.as_str();
+        loop {
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                let Some((_, after)) = comment.split_once("-->") else {
+                    return false;
+                };
+                rest = after.trim_start();
+            } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                let Some((_, after)) = rest.split_once('>') else {
+                    return false;
+                };
+                rest = after.trim_start();
+            } else {
+                break;
+            }
+        }
+        
+        let name = rest.strip_prefix('<').unwrap_or("");
+        let name_end = name.find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/').unwrap_or(name.len());
+        !text.starts_with("<?xml") && ["html", "head", "body"].contains(&&name[..name_end])
+    }
+    
+    /// CSV and TSV rows all split into the same number of fields, tabs taking precedence since
+    /// they rarely turn up in the values of comma separated files. Commas are common enough in
+    /// logs and prose that CSV also needs a header with more than two fields, or a quoted field.
+    fn sniff_delimited(head: &[u8]) -> Option<FormatKind> {
+        let mut lines: Vec<&str> = Self::content_lines(head).take(DELIMITED_SNIFF_LINES + 1).collect();
+        // The sniffed bytes may end part way through a line
+        if lines.len() > 2 {
+            lines.pop();
+        }
+        if lines.len() < 2 {
+            return None;
+        }
+        
+        [FormatKind::Tsv, FormatKind::Csv].into_iter().find(|kind| {
+            let table = TableView::new(kind.delimiter().unwrap_or(','));
+            let field_count = table.parse_row(lines[0]).len();
+            let consistent = field_count > 1 && lines.iter().all(|line| table.parse_row(line).len() == field_count);
+            consistent && (*kind == FormatKind::Tsv || field_count > 2 || lines.iter().any(|line| Self::has_quoted_field(&table, line)))
+        })
+    }
+    
+    fn has_quoted_field(table: &TableView, line: &str) -> bool {
+        let chars: Vec<char> = line.chars().collect();
+        table.parse_row_spans(line).iter().any(|(_, span)| chars.get(span.start) == Some(&'"'))
+    }
+    
+    /// Lines that are neither blank nor comments, which both YAML and TOML start with `#`
+    fn content_lines(head: &[u8]) -> impl Iterator<Item = &str> {
+        head.split(|&byte| byte == b'\n')
+            .filter_map(|line| std::str::from_utf8(line).ok())
+            .map(str::trim_end)
+            .filter(|line| !line.trim_start().is_empty() && !line.trim_start().starts_with('#'))
+    }
+    
+    fn is_bare_key(key: &str) -> bool {
+        key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    }
+    
+    /// `[table]` or `[[array.of.tables]]`
+    fn is_toml_header(line: &str) -> bool {
+        let name = line.strip_prefix("[[").and_then(|line| line.strip_suffix("]]"))
+            .or_else(|| line.strip_prefix('[').and_then(|line| line.strip_suffix(']')));
+        name.is_some_and(|name| Self::is_bare_key(name.trim()))
+    }
+    
+    /// `key = value`
+    fn is_toml_key_value(line: &str) -> bool {
+        line.split_once('=').is_some_and(|(key, value)| Self::is_bare_key(key.trim()) && !value.trim().is_empty())
+    }
+    
+    /// `key: value`, `key:` or `- item`, at any indentation
+    fn is_yaml_entry(line: &str) -> bool {
+        let line = line.trim_start();
+        if line == "-" || line.starts_with("- ") {
+            return true;
+        }
+        let key = match line.split_once(": ") {
+            Some((key, _)) => key,
+            None => line.strip_suffix(':').unwrap_or(""),
+        };
+        Self::is_bare_key(key)
+    }
+    
+    /// Flat `key: value` lines are also headers, logs and notes, so unmarked YAML needs a `key:`
+    /// whose value is indented below it, or is a list, before anything that isn't YAML
+    fn has_yaml_nesting(head: &[u8]) -> bool {
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let lines: Vec<&str> = Self::content_lines(head).take(YAML_SNIFF_LINES).collect();
+        lines.windows(2).take_while(|pair| Self::is_yaml_entry(pair[0])).any(|pair| {
+            let (parent, child) = (pair[0], pair[1]);
+            let child_list = indent(child) == indent(parent) && child.trim_start().starts_with('-');
+            parent.trim_start().strip_suffix(':').is_some_and(Self::is_bare_key)
+                && Self::is_yaml_entry(child)
+                && (indent(child) > indent(parent) || child_list)
+        })
+    }
+    
+    /// JSON Lines start with a complete record on the first line, followed by another record
+    fn is_json_lines(head: &[u8]) -> bool {
+        let Some(first_end) = head.iter().position(|&byte| byte == b'\n') else {
+            return false;
+        };
+        let next_record = head[first_end..].iter().find(|byte| !byte.is_ascii_whitespace());
+        
+        matches!(next_record, Some(b'{' | b'['))
+            && std::str::from_utf8(&head[..first_end]).is_ok_and(|line| FileFormatter::pretty_print_json(line).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_sniff() {
+        assert_eq!(FormatDetector::sniff(b"  \n{\"a\": 1}"), Some(FormatKind::Json));
+        assert_eq!(FormatDetector::sniff(b"\xEF\xBB\xBF[1, 2]"), Some(FormatKind::Json));
+        // A bracket alone isn't enough, the JSON has to hold up to the end of what was read
+        assert_eq!(FormatDetector::sniff(b"[2024-01-01 10:00:00] INFO start"), None);
+        assert_eq!(FormatDetector::sniff(b"{\"a\": [1, {\"b\": \"cut off"), Some(FormatKind::Json));
+        assert_eq!(FormatDetector::sniff(b"{not json}"), None);
+        assert_eq!(FormatDetector::sniff(b"<?xml version=\"1.0\"?><a/>"), Some(FormatKind::Xml));
+        assert_eq!(FormatDetector::sniff(b"<!-- header --><a/>"), Some(FormatKind::Xml));
+        assert_eq!(FormatDetector::sniff(b"\t<root>"), Some(FormatKind::Xml));
+        assert_eq!(FormatDetector::sniff(b"<= not a tag"), None);
+        assert_eq!(FormatDetector::sniff(b"2024-01-01 INFO started"), None);
+        assert_eq!(FormatDetector::sniff(b"   "), None);
+    }
+    
+    #[test]
+    fn test_sniff_yaml_and_toml() {
+        assert_eq!(FormatDetector::sniff(b"---\nkind: Pod\n"), Some(FormatKind::Yaml));
+        assert_eq!(FormatDetector::sniff(b"# manifest\napiVersion: v1\nkind: Service\nmetadata:\n  name: web\n"), Some(FormatKind::Yaml));
+        assert_eq!(FormatDetector::sniff(b"items:\n  - a\n"), Some(FormatKind::Yaml));
+        assert_eq!(FormatDetector::sniff(b"items:\n- a\n"), Some(FormatKind::Yaml));
+        assert_eq!(FormatDetector::sniff(b"[package]\nname = \"x\"\n"), Some(FormatKind::Toml));
+        assert_eq!(FormatDetector::sniff(b"title = \"x\"\nport = 80\n"), Some(FormatKind::Toml));
+        // A lone `key: value` line or free text is not enough
+        assert_eq!(FormatDetector::sniff(b"Note: this is prose.\nIt goes on.\n"), None);
+        // Nor are flat entries, which notes, headers and logs are made of
+        assert_eq!(FormatDetector::sniff(b"- buy milk\n- buy eggs\n"), None);
+        assert_eq!(FormatDetector::sniff(b"Content-Type: text/html\nContent-Length: 5\n"), None);
+        assert_eq!(FormatDetector::sniff(b"INFO: started\nINFO: ready\nWARN:\n  retrying\n"), None);
+        assert_eq!(FormatDetector::sniff(b"[1, 2]"), Some(FormatKind::Json));
+    }
+    
+    #[test]
+    fn test_sniff_web_formats() {
+        assert_eq!(FormatDetector::sniff(b"<!DOCTYPE html><html><body></body></html>"), Some(FormatKind::Html));
+        assert_eq!(FormatDetector::sniff(b"<!-- page -->\n<HTML lang=en>"), Some(FormatKind::Html));
+        assert_eq!(FormatDetector::sniff(b"<?xml version=\"1.0\"?><html xmlns=\"x\"/>"), Some(FormatKind::Xml));
+        // Page elements only count as the first element, and not as the start of a longer name
+        assert_eq!(FormatDetector::sniff(b"<message><header/><body/></message>"), Some(FormatKind::Xml));
+        assert_eq!(FormatDetector::sniff(b"<header><body/></header>"), Some(FormatKind::Xml));
+        
+        assert_eq!(FormatDetector::sniff(b"/*! licence */\n(()=>{var a=1})();"), Some(FormatKind::JavaScript));
+        assert_eq!(FormatDetector::sniff(b"import{a}from\"./a.js\";a();"), Some(FormatKind::JavaScript));
+        assert_eq!(FormatDetector::sniff(b"import os\nimport sys\n"), None);
+        
+        assert_eq!(FormatDetector::sniff(b"/* theme */ body,.nav>a:hover{margin:0}"), Some(FormatKind::Css));
+        assert_eq!(FormatDetector::sniff(b"@media print{a{color:red}}"), Some(FormatKind::Css));
+        assert_eq!(FormatDetector::sniff(b"a: {b: 1}"), None);
+        assert_eq!(FormatDetector::sniff(b"h1,\nh2 {\n  margin: 0;\n}"), Some(FormatKind::Css));
+        assert_eq!(FormatDetector::sniff(b"2024-01-01 request {id: 5, user: bob}"), None);
+        assert_eq!(FormatDetector::sniff(b"GET request {id: 5, user: bob}"), None);
+        assert_eq!(FormatDetector::sniff(b"started\nretrying\nrequest {id: 5}"), None);
+    }
+    
+    #[test]
+    fn test_sniff_delimited() {
+        assert_eq!(FormatDetector::sniff(b"id,name\n1,\"Smith, J\"\n2,Jones\n"), Some(FormatKind::Csv));
+        assert_eq!(FormatDetector::sniff(b"id\tname, title\n1\tx\n"), Some(FormatKind::Tsv));
+        // Rows must agree on their field count
+        assert_eq!(FormatDetector::sniff(b"a,b\nc\nd,e\nf,g\n"), None);
+        assert_eq!(FormatDetector::sniff(b"just one line, with a comma\n"), None);
+        // Two unquoted fields could be anything with one comma per line
+        assert_eq!(FormatDetector::sniff(b"Hello, world\nGoodbye, moon\n"), None);
+        assert_eq!(FormatDetector::sniff(b"2024-01-01 10:00:00,123 INFO a\n2024-01-01 10:00:01,456 INFO b\n"), None);
+        assert_eq!(FormatDetector::sniff(b"id,name,age\n1,Smith,40\n"), Some(FormatKind::Csv));
+        assert_eq!(FormatDetector::sniff(b"\"id\",name\n1,Smith\n"), Some(FormatKind::Csv));
+    }
+    
+    #[test]
+    fn test_sniff_json_lines() {
+        assert_eq!(FormatDetector::sniff(b"{\"a\": 1}\n{\"a\": 2}\n"), Some(FormatKind::JsonLines));
+        assert_eq!(FormatDetector::sniff(b"[1]\r\n\n[2]"), Some(FormatKind::JsonLines));
+        // A pretty-printed document or a lone record is ordinary JSON
+        assert_eq!(FormatDetector::sniff(b"{\n  \"a\": 1\n}\n"), Some(FormatKind::Json));
+        assert_eq!(FormatDetector::sniff(b"[{\"a\": 1},\n{\"a\": 2}]"), Some(FormatKind::Json));
+        assert_eq!(FormatDetector::sniff(b"{\"a\": 1}\n"), Some(FormatKind::Json));
+    }
+}
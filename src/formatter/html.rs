@@ -0,0 +1,344 @@
+use super::code::{find, CodeFormatter, CodeLanguage};
+use super::sink::FormatSink;
+use crate::file_reader::ProgressCallback;
+use anyhow::Result;
+
+const INDENT: &[u8] = b"    ";
+
+/// Elements that never have content or an end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose content is not markup, and runs to their end tag whatever it holds
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea"];
+
+/// Block elements that end an open `<p>` when they start
+const PARAGRAPH_CLOSERS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "details", "div", "dl", "fieldset", "figure", "footer", "form", "h1",
+    "h2", "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+/// Re-indenting HTML formatter that copes with the markup browsers accept.
+///
+/// Tags are matched the way browsers match them rather than strictly: names are compared
+/// without case, void elements such as `<br>` need no end tag, elements like `<li>` and `<p>`
+/// are closed by the next sibling that can't go inside them, and an end tag closes any
+/// elements still open inside its element. End tags with nothing to close are kept where
+/// they are. HTML is never rejected.
+///
+/// As with XML, nodes are copied verbatim and only whitespace between elements is replaced
+/// by indentation. Elements with text of their own and `<pre>` keep their content as it is.
+/// The contents of `<script>` and `<style>` are re-indented as JavaScript and CSS.
+pub struct HtmlFormatter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Text,
+    /// Comments, doctypes and processing instructions
+    Other,
+    Start { name: String, self_closing: bool },
+    End { name: String },
+}
+
+/// Formatting state of an open element
+#[derive(Debug, Clone, Default)]
+struct OpenElement {
+    name: String,
+    preserve_space: bool,
+    has_text: bool,
+    has_children: bool,
+}
+
+impl OpenElement {
+    /// Whether the element's content is copied as it is, without adding line breaks
+    fn is_inline(&self) -> bool {
+        self.preserve_space || self.has_text
+    }
+}
+
+impl HtmlFormatter {
+    pub fn format(input: &[u8], output: &mut FormatSink, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        let mut open: Vec<OpenElement> = Vec::new();
+        let mut pos = 0;
+        let total_bytes = input.len();
+        let progress_interval = (total_bytes / 20).max(1); // Update every 5%
+        let mut last_progress_pos = 0;
+        
+        while pos < input.len() {
+            let start = pos;
+            let node = Self::next_node(input, &mut pos);
+            let raw = &input[start..pos];
+            let offset = start as u64;
+            let inline = open.last().is_some_and(OpenElement::is_inline);
+            
+            match node {
+                Node::Text if raw.iter().all(u8::is_ascii_whitespace) => {
+                    // Indentation between elements takes the place of whitespace-only text
+                    if inline {
+                        output.write_verbatim(raw, offset)?;
+                    }
+                }
+                Node::Text => {
+                    if let Some(parent) = open.last_mut() {
+                        parent.has_text = true;
+                        parent.has_children = true;
+                    }
+                    output.write_verbatim(raw, offset)?;
+                }
+                Node::End { name } => match open.iter().rposition(|element| element.name == name) {
+                    Some(index) => {
+                        // Elements left open inside this one end with it
+                        let element = open.drain(index..).next().unwrap_or_default();
+                        if !element.is_inline() && element.has_children {
+                            output.start_line(open.len(), INDENT, offset)?;
+                        }
+                        output.write_verbatim(raw, offset)?;
+                    }
+                    None => {
+                        if !inline {
+                            output.start_line(open.len(), INDENT, offset)?;
+                        }
+                        output.write_verbatim(raw, offset)?;
+                    }
+                },
+                Node::Other => {
+                    if let Some(parent) = open.last_mut() {
+                        parent.has_children = true;
+                    }
+                    if !inline {
+                        output.start_line(open.len(), INDENT, offset)?;
+                    }
+                    output.write_verbatim(raw, offset)?;
+                }
+                Node::Start { name, self_closing } => {
+                    while open.last().is_some_and(|element| Self::is_closed_by(&element.name, &name)) {
+                        open.pop();
+                    }
+                    let inline = open.last().is_some_and(OpenElement::is_inline);
+                    if let Some(parent) = open.last_mut() {
+                        parent.has_children = true;
+                    }
+                    if !inline {
+                        output.start_line(open.len(), INDENT, offset)?;
+                    }
+                    output.write_verbatim(raw, offset)?;
+                    
+                    if self_closing || VOID_ELEMENTS.contains(&name.as_str()) {
+                        // Nothing to open
+                    } else if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                        Self::write_raw_text(input, &mut pos, &name, raw, open.len(), inline, output)?;
+                    } else {
+                        let preserve_space = name == "pre" || open.last().is_some_and(|parent| parent.preserve_space);
+                        open.push(OpenElement { name, preserve_space, ..OpenElement::default() });
+                    }
+                }
+            }
+            
+            if let Some(callback) = progress_callback {
+                if start > last_progress_pos + progress_interval {
+                    callback(start as f64 / total_bytes as f64, "Formatting HTML...");
+                    last_progress_pos = start;
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Write the content of a `<script>`, `<style>` or `<textarea>` and its end tag, leaving
+    /// `pos` after them
+    fn write_raw_text(input: &[u8], pos: &mut usize, name: &str, start_tag: &[u8], depth: usize, inline: bool, output: &mut FormatSink) -> Result<()> {
+        let content_start = *pos;
+        let content_end = Self::find_end_tag(input, content_start, name).unwrap_or(input.len());
+        let content = &input[content_start..content_end];
+        *pos = content_end;
+        
+        let language = match name {
+            "script" if Self::is_script(start_tag) => Some(CodeLanguage::JavaScript),
+            "style" => Some(CodeLanguage::Css),
+            _ => None,
+        };
+        let indented = match language {
+            Some(language) if !inline && !content.iter().all(u8::is_ascii_whitespace) => {
+                output.start_line(depth + 1, INDENT, content_start as u64)?;
+                CodeFormatter::format_embedded(content, content_start as u64, depth + 1, language, output)?;
+                true
+            }
+            _ => {
+                output.write_verbatim(content, content_start as u64)?;
+                false
+            }
+        };
+        
+        if content_end < input.len() {
+            Self::next_node(input, pos);
+            if indented {
+                output.start_line(depth, INDENT, content_end as u64)?;
+            }
+            output.write_verbatim(&input[content_end..*pos], content_end as u64)?;
+        }
+        Ok(())
+    }
+    
+    /// Scripts without a type, or with a JavaScript or JSON one. Templates and other data are
+    /// left alone.
+    fn is_script(start_tag: &[u8]) -> bool {
+        let tag = String::from_utf8_lossy(start_tag).to_ascii_lowercase();
+        match tag.find("type=") {
+            Some(index) => ["javascript", "module", "json", "ecmascript"].iter().any(|kind| tag[index..].contains(kind)),
+            None => true,
+        }
+    }
+    
+    /// Whether the start of `starting` ends an open `open` element, as `<li>` ends an `<li>`
+    fn is_closed_by(open: &str, starting: &str) -> bool {
+        match open {
+            "p" => PARAGRAPH_CLOSERS.contains(&starting),
+            "li" => starting == "li",
+            "dt" | "dd" => matches!(starting, "dt" | "dd"),
+            "td" | "th" => matches!(starting, "td" | "th" | "tr"),
+            "tr" => starting == "tr",
+            "option" => matches!(starting, "option" | "optgroup"),
+            _ => false,
+        }
+    }
+    
+    /// Where the end tag of a raw text element starts, matching its name without case
+    fn find_end_tag(input: &[u8], from: usize, name: &str) -> Option<usize> {
+        let mut pos = from;
+        while let Some(index) = input[pos..].windows(2).position(|window| window == b"</") {
+            let tag_start = pos + index;
+            let name_end = tag_start + 2 + name.len();
+            if input.get(tag_start + 2..name_end).is_some_and(|tag_name| tag_name.eq_ignore_ascii_case(name.as_bytes()))
+                && !input.get(name_end).is_some_and(|&byte| byte.is_ascii_alphanumeric())
+            {
+                return Some(tag_start);
+            }
+            pos = tag_start + 2;
+        }
+        None
+    }
+    
+    /// Read the node at `pos` and move past it
+    fn next_node(input: &[u8], pos: &mut usize) -> Node {
+        let start = *pos;
+        let rest = &input[start..];
+        let next = rest.get(1).copied();
+        
+        if rest.starts_with(b"<!--") {
+            *pos = find(input, start + 4, b"-->").map_or(input.len(), |end| end + 3);
+            Node::Other
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"<?") {
+            *pos = find(input, start, b">").map_or(input.len(), |end| end + 1);
+            Node::Other
+        } else if next == Some(b'/') && Self::starts_tag(rest) {
+            *pos = find(input, start, b">").map_or(input.len(), |end| end + 1);
+            Node::End { name: Self::tag_name(&rest[2..]) }
+        } else if rest[0] == b'<' && Self::starts_tag(rest) {
+            *pos = Self::find_tag_end(input, start);
+            Node::Start { name: Self::tag_name(&rest[1..]), self_closing: input[start..*pos].ends_with(b"/>") }
+        } else {
+            // Text runs to the next `<` that starts a tag, a lone `<` is part of it
+            let mut end = start + 1;
+            *pos = loop {
+                match input[end..].iter().position(|&byte| byte == b'<') {
+                    None => break input.len(),
+                    Some(index) if Self::starts_tag(&input[end + index..]) => break end + index,
+                    Some(index) => end += index + 1,
+                }
+            };
+            Node::Text
+        }
+    }
+    
+    fn starts_tag(rest: &[u8]) -> bool {
+        match rest.get(1) {
+            Some(b'!' | b'?') => true,
+            Some(b'/') => rest.get(2).is_some_and(u8::is_ascii_alphabetic),
+            next => next.is_some_and(u8::is_ascii_alphabetic),
+        }
+    }
+    
+    /// The end of a start tag, after its `>`. Quoted attribute values may contain `>`.
+    fn find_tag_end(input: &[u8], start: usize) -> usize {
+        let mut quote = None;
+        for (index, &byte) in input.iter().enumerate().skip(start) {
+            match (quote, byte) {
+                (Some(open), _) if byte == open => quote = None,
+                (Some(_), _) => {}
+                (None, b'"' | b'\'') => quote = Some(byte),
+                (None, b'>') => return index + 1,
+                (None, _) => {}
+            }
+        }
+        input.len()
+    }
+    
+    fn tag_name(bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|&byte| !(byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b':' | b'_'))).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn format(input: &str) -> String {
+        let mut output = Vec::new();
+        let mut sink = FormatSink::new(&mut output, std::io::sink()).unwrap();
+        HtmlFormatter::format(input.as_bytes(), &mut sink, None).unwrap();
+        sink.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+    
+    #[test]
+    fn test_reindents_tolerantly() {
+        let input = "<!DOCTYPE html><HTML><head><meta charset=utf-8><title>T</title></head><body><ul><li>one<li>two</ul><p>Some <b>bold</b> text<div data-x=\"a>b\"><br><img src=x /></span></div></body></html>";
+        let expected = "\
+<!DOCTYPE html>
+<HTML>
+    <head>
+        <meta charset=utf-8>
+        <title>T</title>
+    </head>
+    <body>
+        <ul>
+            <li>one
+            <li>two
+        </ul>
+        <p>Some <b>bold</b> text
+        <div data-x=\"a>b\">
+            <br>
+            <img src=x />
+            </span>
+        </div>
+    </body>
+</html>";
+        assert_eq!(format(input), expected);
+    }
+    
+    #[test]
+    fn test_raw_text_elements() {
+        let input = "<div><script>if(a){b()}</script><script type=\"text/template\"><p>{{x}}</p></script><style>a{color:red}</style><pre>  keep\n <i>this</i></pre><script src=x></script></div>";
+        let expected = "\
+<div>
+    <script>
+        if(a){
+            b()
+        }
+    </script>
+    <script type=\"text/template\"><p>{{x}}</p></script>
+    <style>
+        a{
+            color:red
+        }
+    </style>
+    <pre>  keep
+ <i>this</i></pre>
+    <script src=x></script>
+</div>";
+        assert_eq!(format(input), expected);
+    }
+}
@@ -0,0 +1,439 @@
+use super::sink::FormatSink;
+use super::SyntaxError;
+use crate::file_reader::ProgressCallback;
+use anyhow::Result;
+use std::io::{BufRead, BufReader, Read, Write};
+
+const INDENT: &[u8] = b"  ";
+
+/// Streaming JSON pretty-printer.
+///
+/// The input is read in buffered chunks and re-emitted token by token, so memory use
+/// only grows with the nesting depth of the document, never with its size. Strings and
+/// numbers are copied through verbatim; only the whitespace between tokens changes.
+/// The output layout matches `serde_json::to_string_pretty`.
+pub struct JsonFormatter;
+
+/// What the parser expects to see next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    /// Any value, e.g. at the start of the document or after a colon
+    Value,
+    /// First element of an array, or its end
+    ValueOrEnd,
+    /// First key of an object, or its end
+    KeyOrEnd,
+    /// A key after a comma
+    Key,
+    Colon,
+    CommaOrEnd,
+    /// The top-level value is complete, only whitespace may follow
+    Done,
+}
+
+impl JsonFormatter {
+    pub fn format<R: Read>(input: R, output: &mut FormatSink, total_bytes: u64, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        let mut stream = JsonStream::new(input, output, total_bytes, progress_callback);
+        stream.report_progress(0.0);
+        stream.run()?;
+        stream.report_progress(1.0);
+        Ok(())
+    }
+    
+    /// Whether the first bytes of a file are valid JSON up to the end of its first value, or up
+    /// to where they were cut off
+    pub fn is_json_start(head: &[u8]) -> bool {
+        let Ok(mut output) = FormatSink::new(std::io::sink(), std::io::sink()) else {
+            return false;
+        };
+        let mut stream = JsonStream::new(head, &mut output, head.len() as u64, None);
+        stream.run().is_ok() || stream.expect == Expect::Done || stream.offset >= head.len() as u64
+    }
+}
+
+struct JsonStream<'a, 'b, R: Read> {
+    input: BufReader<R>,
+    output: &'a mut FormatSink<'b>,
+    /// Open containers, `b'{'` or `b'['`
+    containers: Vec<u8>,
+    expect: Expect,
+    offset: u64,
+    line: u64,
+    column: u64,
+    /// Position of the most recently consumed byte, reported in errors
+    error_position: (u64, u64),
+    total_bytes: u64,
+    last_progress_offset: u64,
+    progress_interval: u64,
+    progress_callback: Option<&'a ProgressCallback>,
+}
+
+impl<'a, 'b, R: Read> JsonStream<'a, 'b, R> {
+    fn new(input: R, output: &'a mut FormatSink<'b>, total_bytes: u64, progress_callback: Option<&'a ProgressCallback>) -> Self {
+        JsonStream {
+            input: BufReader::with_capacity(64 * 1024, input),
+            output,
+            containers: Vec::new(),
+            expect: Expect::Value,
+            offset: 0,
+            line: 1,
+            column: 1,
+            error_position: (1, 1),
+            total_bytes,
+            last_progress_offset: 0,
+            progress_interval: (total_bytes / 20).max(1), // Update every 5%
+            progress_callback,
+        }
+    }
+    
+    fn run(&mut self) -> Result<()> {
+        while let Some(byte) = self.next_token_byte()? {
+            self.output.set_source_offset(self.offset - 1);
+            match self.expect {
+                Expect::Done => return Err(self.error("trailing characters")),
+                Expect::Colon => {
+                    if byte != b':' {
+                        return Err(self.error("expected `:`"));
+                    }
+                    self.output.write_all(b": ")?;
+                    self.expect = Expect::Value;
+                }
+                Expect::CommaOrEnd => match byte {
+                    b',' => {
+                        self.output.write_all(b",")?;
+                        self.write_newline(self.containers.len())?;
+                        self.expect = if self.containers.last() == Some(&b'{') { Expect::Key } else { Expect::Value };
+                    }
+                    b'}' | b']' => self.close_container(byte, false)?,
+                    _ => return Err(self.error("expected `,` or end of container")),
+                },
+                Expect::KeyOrEnd | Expect::Key => {
+                    if self.expect == Expect::KeyOrEnd && byte == b'}' {
+                        self.close_container(byte, true)?;
+                        continue;
+                    }
+                    if byte != b'"' {
+                        return Err(self.error("key must be a string"));
+                    }
+                    if self.expect == Expect::KeyOrEnd {
+                        self.write_newline(self.containers.len())?;
+                    }
+                    self.copy_string()?;
+                    self.expect = Expect::Colon;
+                }
+                Expect::Value | Expect::ValueOrEnd => {
+                    if self.expect == Expect::ValueOrEnd {
+                        if byte == b']' {
+                            self.close_container(byte, true)?;
+                            continue;
+                        }
+                        self.write_newline(self.containers.len())?;
+                    }
+                    self.value(byte)?;
+                }
+            }
+        }
+        
+        if self.expect != Expect::Done {
+            return Err(self.error("unexpected end of input"));
+        }
+        Ok(())
+    }
+    
+    /// Handle a value starting with `byte`, which has already been consumed
+    fn value(&mut self, byte: u8) -> Result<()> {
+        match byte {
+            b'{' | b'[' => {
+                self.output.write_all(&[byte])?;
+                self.containers.push(byte);
+                self.expect = if byte == b'{' { Expect::KeyOrEnd } else { Expect::ValueOrEnd };
+                return Ok(());
+            }
+            b'"' => self.copy_string()?,
+            b'-' | b'0'..=b'9' => self.copy_number(byte)?,
+            b't' => self.copy_literal(b"true")?,
+            b'f' => self.copy_literal(b"false")?,
+            b'n' => self.copy_literal(b"null")?,
+            _ => return Err(self.error("expected value")),
+        }
+        self.finish_value();
+        Ok(())
+    }
+    
+    fn finish_value(&mut self) {
+        self.expect = if self.containers.is_empty() { Expect::Done } else { Expect::CommaOrEnd };
+    }
+    
+    fn close_container(&mut self, byte: u8, empty: bool) -> Result<()> {
+        let open = self.containers.pop();
+        let matches = matches!((open, byte), (Some(b'{'), b'}') | (Some(b'['), b']'));
+        if !matches {
+            return Err(self.error("mismatched closing bracket"));
+        }
+        
+        if !empty {
+            self.write_newline(self.containers.len())?;
+        }
+        self.output.write_all(&[byte])?;
+        self.finish_value();
+        Ok(())
+    }
+    
+    fn write_newline(&mut self, depth: usize) -> Result<()> {
+        self.output.write_all(b"\n")?;
+        for _ in 0..depth {
+            self.output.write_all(INDENT)?;
+        }
+        Ok(())
+    }
+    
+    /// Copy a string whose opening quote was already consumed, validating escapes
+    fn copy_string(&mut self) -> Result<()> {
+        self.output.write_all(b"\"")?;
+        loop {
+            // Copy plain runs straight from the read buffer
+            let buffer = self.input.fill_buf()?;
+            if buffer.is_empty() {
+                return Err(self.error("unterminated string"));
+            }
+            let run = buffer.iter().position(|&b| b == b'"' || b == b'\\' || b < 0x20).unwrap_or(buffer.len());
+            if run > 0 {
+                self.output.write_all(&buffer[..run])?;
+                self.input.consume(run);
+                self.offset += run as u64;
+                self.column += run as u64;
+                continue;
+            }
+            
+            match self.next_byte()? {
+                Some(b'"') => {
+                    self.output.write_all(b"\"")?;
+                    return Ok(());
+                }
+                Some(b'\\') => {
+                    let escaped = self.next_byte()?.ok_or_else(|| self.error("unterminated string"))?;
+                    match escaped {
+                        b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {
+                            self.output.write_all(&[b'\\', escaped])?;
+                        }
+                        b'u' => {
+                            let mut hex = [b'u'; 5];
+                            for digit in hex.iter_mut().skip(1) {
+                                *digit = self.next_byte()?
+                                    .filter(u8::is_ascii_hexdigit)
+                                    .ok_or_else(|| self.error("invalid unicode escape"))?;
+                            }
+                            self.output.write_all(b"\\")?;
+                            self.output.write_all(&hex)?;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => return Err(self.error("control character in string")),
+            }
+        }
+    }
+    
+    /// Copy a number whose first byte was already consumed, validating its grammar
+    fn copy_number(&mut self, first: u8) -> Result<()> {
+        self.output.write_all(&[first])?;
+        
+        let int_start = if first == b'-' {
+            self.next_byte()?.filter(u8::is_ascii_digit).ok_or_else(|| self.error("invalid number"))?
+        } else {
+            first
+        };
+        if first == b'-' {
+            self.output.write_all(&[int_start])?;
+        }
+        if int_start != b'0' {
+            self.copy_digits()?;
+        }
+        
+        if self.peek_byte()? == Some(b'.') {
+            self.copy_next()?;
+            if self.copy_digits()? == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        
+        if matches!(self.peek_byte()?, Some(b'e') | Some(b'E')) {
+            self.copy_next()?;
+            if matches!(self.peek_byte()?, Some(b'+') | Some(b'-')) {
+                self.copy_next()?;
+            }
+            if self.copy_digits()? == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn copy_digits(&mut self) -> Result<usize> {
+        let mut count = 0;
+        while matches!(self.peek_byte()?, Some(b'0'..=b'9')) {
+            self.copy_next()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+    
+    fn copy_next(&mut self) -> Result<()> {
+        if let Some(byte) = self.next_byte()? {
+            self.output.write_all(&[byte])?;
+        }
+        Ok(())
+    }
+    
+    /// Copy a literal whose first byte was already consumed
+    fn copy_literal(&mut self, literal: &[u8]) -> Result<()> {
+        for &expected in &literal[1..] {
+            if self.next_byte()? != Some(expected) {
+                return Err(self.error("invalid literal"));
+            }
+        }
+        self.output.write_all(literal)?;
+        Ok(())
+    }
+    
+    /// Next byte that is not whitespace between tokens
+    fn next_token_byte(&mut self) -> Result<Option<u8>> {
+        loop {
+            match self.next_byte()? {
+                Some(b' ' | b'\t' | b'\n' | b'\r') => continue,
+                other => return Ok(other),
+            }
+        }
+    }
+    
+    fn peek_byte(&mut self) -> Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+    
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        let byte = self.peek_byte()?;
+        if let Some(byte) = byte {
+            self.input.consume(1);
+            self.offset += 1;
+            self.error_position = (self.line, self.column);
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            
+            if self.offset >= self.last_progress_offset + self.progress_interval {
+                self.last_progress_offset = self.offset;
+                self.report_progress(self.offset as f64 / self.total_bytes.max(1) as f64);
+            }
+        }
+        Ok(byte)
+    }
+    
+    fn report_progress(&self, progress: f64) {
+        if let Some(callback) = self.progress_callback {
+            callback(progress.min(1.0), "Formatting JSON...");
+        }
+    }
+    
+    fn error(&self, message: &str) -> anyhow::Error {
+        let (line, column) = self.error_position;
+        SyntaxError {
+            offset: self.offset.saturating_sub(1),
+            message: format!("Failed to parse JSON: {} at line {} column {}", message, line, column),
+        }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn format_with_map(input: &str) -> Result<(String, Vec<u64>)> {
+        let mut output = Vec::new();
+        let mut line_map = Vec::new();
+        let mut sink = FormatSink::new(&mut output, &mut line_map)?;
+        JsonFormatter::format(input.as_bytes(), &mut sink, input.len() as u64, None)?;
+        sink.finish()?;
+        
+        let offsets = line_map
+            .chunks(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok((String::from_utf8(output).unwrap(), offsets))
+    }
+    
+    fn format(input: &str) -> Result<String> {
+        format_with_map(input).map(|(output, _)| output)
+    }
+    
+    fn assert_matches_serde(input: &str) {
+        let value: serde_json::Value = serde_json::from_str(input).unwrap();
+        assert_eq!(format(input).unwrap(), serde_json::to_string_pretty(&value).unwrap());
+    }
+    
+    #[test]
+    fn test_format_matches_serde_layout() {
+        assert_matches_serde(r#"{"a":1,"b":[true,false,null],"c":{"d":"e"}}"#);
+        assert_matches_serde(r#"  [ {} , [ ] , { "x" : [ { } ] } ]  "#);
+        assert_matches_serde(r#"{"nested":{"deeper":{"deepest":[1,-2,3.5,-0.25]}}}"#);
+        assert_matches_serde(r#""just a string""#);
+        assert_matches_serde("42");
+        assert_matches_serde(r#"{"quote":"a\"b","slash":"a\\b","unicode":"日本語"}"#);
+    }
+    
+    #[test]
+    fn test_format_keeps_key_order() {
+        assert_eq!(format(r#"{"b":1,"a":2}"#).unwrap(), "{\n  \"b\": 1,\n  \"a\": 2\n}");
+    }
+    
+    #[test]
+    fn test_format_keeps_numbers_verbatim() {
+        assert_eq!(format("[1e10,-0.25E-3,1.50]").unwrap(), "[\n  1e10,\n  -0.25E-3,\n  1.50\n]");
+    }
+    
+    #[test]
+    fn test_format_keeps_escapes_verbatim() {
+        assert_eq!(format(r#"["\u00e9\n\/"]"#).unwrap(), "[\n  \"\\u00e9\\n\\/\"\n]");
+    }
+    
+    #[test]
+    fn test_format_rejects_invalid_json() {
+        for input in ["", "{", "[1,]", r#"{"a" 1}"#, r#"{"a":1}}"#, "[1] [2]", "tru", "01", "1.", "-", r#"["\x"]"#, "[}", r#"{1:2}"#] {
+            assert!(format(input).is_err(), "accepted {:?}", input);
+        }
+    }
+    
+    #[test]
+    fn test_format_line_map() {
+        let input = r#"{"a": [1, 2], "b": {}}"#;
+        let (output, offsets) = format_with_map(input).unwrap();
+        assert_eq!(output.lines().count(), offsets.len());
+        
+        // Every formatted line maps back to the token it starts with
+        let tokens: Vec<&str> = offsets.iter().map(|&offset| &input[offset as usize..offset as usize + 1]).collect();
+        assert_eq!(tokens, vec!["{", "\"", "1", "2", "]", "\"", "}"]);
+    }
+    
+    #[test]
+    fn test_format_error_position() {
+        let error = format("{\n  \"a\": tru\n}").unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{}", error);
+        
+        // The offset points at the byte that broke the grammar, so the raw view can jump to it
+        let input = r#"{"a": 1, "b" 2}"#;
+        let error = format(input).unwrap_err().downcast::<SyntaxError>().unwrap();
+        assert_eq!(&input[error.offset as usize..], "2}");
+    }
+    
+    #[test]
+    fn test_format_keeps_output_before_error() {
+        let mut output = Vec::new();
+        let mut sink = FormatSink::new(&mut output, std::io::sink()).unwrap();
+        assert!(JsonFormatter::format(&br#"[{"a": 1}, {"b": "#[..], &mut sink, 17, None).is_err());
+        sink.finish().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "[\n  {\n    \"a\": 1\n  },\n  {\n    \"b\": ");
+    }
+}
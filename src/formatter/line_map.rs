@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+const ENTRY_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Memory-mapped mapping from formatted lines to byte offsets in the original file,
+/// as written by `FormatSink`. Offsets never decrease from one line to the next.
+pub struct LineMap {
+    mmap: Mmap,
+}
+
+impl LineMap {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open line map: {}", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| "Failed to memory-map line map")?;
+        Ok(Self { mmap })
+    }
+    
+    pub fn line_count(&self) -> usize {
+        self.mmap.len() / ENTRY_SIZE
+    }
+    
+    /// Byte offset in the original file where a formatted line's content came from
+    pub fn source_offset(&self, line: usize) -> u64 {
+        if self.line_count() == 0 {
+            return 0;
+        }
+        let index = line.min(self.line_count() - 1) * ENTRY_SIZE;
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes.copy_from_slice(&self.mmap[index..index + ENTRY_SIZE]);
+        u64::from_le_bytes(bytes)
+    }
+    
+    /// The first formatted line whose content starts at or after `offset` in the original file
+    pub fn line_for_source_offset(&self, offset: u64) -> usize {
+        let (mut low, mut high) = (0, self.line_count());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.source_offset(mid) < offset {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low.min(self.line_count().saturating_sub(1))
+    }
+}
@@ -0,0 +1,234 @@
+mod anchor;
+mod cache;
+mod code;
+mod detect;
+mod html;
+mod json;
+mod line_map;
+mod sink;
+mod toml;
+mod xml;
+mod yaml;
+
+use crate::file_reader::{FileReader, ProgressCallback};
+use anyhow::{bail, Context, Result};
+use cache::FormatCache;
+use code::{CodeFormatter, CodeLanguage};
+pub use detect::FormatDetector;
+use html::HtmlFormatter;
+use json::JsonFormatter;
+pub use line_map::LineMap;
+use sink::FormatSink;
+use self::toml::TomlFormatter;
+use xml::XmlFormatter;
+use yaml::YamlFormatter;
+use memmap2::Mmap;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+/// Structured formats that can be pretty-printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatKind {
+    Json,
+    /// One JSON record per line. Records are expanded one at a time instead of formatting the file.
+    JsonLines,
+    Xml,
+    Yaml,
+    Toml,
+    /// Comma-separated values, shown as a table rather than formatted
+    Csv,
+    /// Tab-separated values, shown as a table rather than formatted
+    Tsv,
+    Html,
+    JavaScript,
+    Css,
+}
+
+impl FormatKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            FormatKind::Json => "json",
+            FormatKind::JsonLines => "jsonl",
+            FormatKind::Xml => "xml",
+            FormatKind::Yaml => "yaml",
+            FormatKind::Toml => "toml",
+            FormatKind::Csv => "csv",
+            FormatKind::Tsv => "tsv",
+            FormatKind::Html => "html",
+            FormatKind::JavaScript => "js",
+            FormatKind::Css => "css",
+        }
+    }
+    
+    /// Whether the file as a whole can be pretty-printed into a formatted copy
+    pub fn has_formatted_view(&self) -> bool {
+        !matches!(self, FormatKind::JsonLines | FormatKind::Csv | FormatKind::Tsv)
+    }
+    
+    /// The field separator of a delimited format
+    pub fn delimiter(&self) -> Option<char> {
+        match self {
+            FormatKind::Csv => Some(','),
+            FormatKind::Tsv => Some('\t'),
+            _ => None,
+        }
+    }
+}
+
+/// A syntax error that stopped a formatter part way, at a byte offset into its input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub offset: u64,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// A formatted copy on disk together with its line map
+#[derive(Debug, Clone)]
+pub struct FormattedFile {
+    pub path: PathBuf,
+    pub line_map_path: PathBuf,
+    /// Set when the input is malformed, in which case the copy only covers the input up to the error
+    pub error: Option<SyntaxError>,
+}
+
+impl FormattedFile {
+    pub fn open(&self, progress_callback: Option<ProgressCallback>) -> Result<FormattedSource> {
+        Ok(FormattedSource {
+            reader: FileReader::new_with_progress(&self.path, progress_callback)?,
+            line_map: LineMap::open(&self.line_map_path)?,
+            error: self.error.clone(),
+        })
+    }
+}
+
+/// An opened formatted copy, ready to be shown next to the raw file
+pub struct FormattedSource {
+    pub reader: FileReader,
+    pub line_map: LineMap,
+    pub error: Option<SyntaxError>,
+}
+
+pub struct FileFormatter;
+
+impl FileFormatter {
+    /// Create a formatted copy of the file.
+    ///
+    /// The copy goes to `output_path` when given, otherwise into the format cache where an
+    /// earlier copy of the same content is reused. The original's directory is never touched.
+    /// Line maps always live in the cache.
+    ///
+    /// Malformed input is formatted on a best-effort basis: everything before the first syntax
+    /// error is kept, and the error is returned with the copy rather than as a failure.
+    ///
+    /// Setting `cancel` fails the call at its next check, without storing anything. Temporary
+    /// files are named after `task_id`, so a cancelled call can't touch those of the next one.
+    pub fn format_file(path: &Path, kind: FormatKind, output_path: Option<&Path>, progress_callback: Option<ProgressCallback>, task_id: u64, cancel: &AtomicBool) -> Result<FormattedFile> {
+        if !kind.has_formatted_view() {
+            bail!("{:?} files are not formatted as a whole", kind);
+        }
+        
+        let progress_callback = progress_callback.as_ref();
+        let cache = FormatCache::open()?;
+        let entry = cache.entry_path(path, kind.extension(), progress_callback, cancel)?;
+        let line_map_path = FormatCache::line_map_path(&entry);
+        
+        let (formatted_path, error) = match output_path {
+            Some(output_path) => {
+                if Self::is_same_file(path, output_path) {
+                    bail!("Output path must differ from the input file: {}", output_path.display());
+                }
+                let error = FormatCache::store(output_path, &line_map_path, task_id, |output, line_map| {
+                    Self::write_formatted(path, kind, output, line_map, progress_callback, cancel)
+                })?;
+                (output_path.to_path_buf(), error)
+            }
+            None => {
+                let error = if cache.lookup(&entry) {
+                    FormatCache::load_error(&entry)
+                } else {
+                    cache.prune();
+                    FormatCache::store(&entry, &line_map_path, task_id, |output, line_map| {
+                        let error = Self::write_formatted(path, kind, output, line_map, progress_callback, cancel)?;
+                        // Recorded before the entry is moved into place, so a hit always knows whether it is partial
+                        FormatCache::save_error(&entry, error.as_ref())?;
+                        Ok(error)
+                    })?
+                };
+                (entry, error)
+            }
+        };
+        
+        Ok(FormattedFile { path: formatted_path, line_map_path, error })
+    }
+    
+    /// Pretty-print a single JSON value held in memory, such as one record of a JSON Lines file
+    pub fn pretty_print_json(text: &str) -> Result<String> {
+        let mut output = Vec::with_capacity(text.len() * 2);
+        let mut sink = FormatSink::new(&mut output, std::io::sink())?;
+        JsonFormatter::format(text.as_bytes(), &mut sink, text.len() as u64, None)?;
+        sink.finish()?;
+        Ok(String::from_utf8(output)?)
+    }
+    
+    fn is_same_file(a: &Path, b: &Path) -> bool {
+        match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+    
+    /// Write the formatted copy and its line map. A syntax error in the input is returned once
+    /// the output up to it has been written; any other failure is an error.
+    fn write_formatted(input_path: &Path, kind: FormatKind, output_path: &Path, line_map_path: &Path, progress_callback: Option<&ProgressCallback>, cancel: &AtomicBool) -> Result<Option<SyntaxError>> {
+        let input = File::open(input_path)
+            .with_context(|| format!("Failed to read file: {}", input_path.display()))?;
+        let total_bytes = input.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        
+        let output = File::create(output_path)
+            .with_context(|| format!("Failed to write formatted file: {}", output_path.display()))?;
+        let line_map = File::create(line_map_path)
+            .with_context(|| format!("Failed to write line map: {}", line_map_path.display()))?;
+        let mut sink = FormatSink::new(output, line_map)?;
+        sink.set_cancel(cancel);
+        
+        // Mapping the input can take a while, as can the parse that YAML and TOML start with
+        sink.check_cancel()?;
+        
+        // Format based on file type
+        let result = match kind {
+            FormatKind::Json => JsonFormatter::format(input, &mut sink, total_bytes, progress_callback),
+            FormatKind::Xml => XmlFormatter::format(&Self::map_input(&input, input_path)?, &mut sink, progress_callback),
+            FormatKind::Html => HtmlFormatter::format(&Self::map_input(&input, input_path)?, &mut sink, progress_callback),
+            FormatKind::JavaScript => {
+                CodeFormatter::format(&Self::map_input(&input, input_path)?, CodeLanguage::JavaScript, &mut sink, progress_callback)
+            }
+            FormatKind::Css => CodeFormatter::format(&Self::map_input(&input, input_path)?, CodeLanguage::Css, &mut sink, progress_callback),
+            FormatKind::Yaml => YamlFormatter::format(input, &mut sink, progress_callback),
+            FormatKind::Toml => TomlFormatter::format(input, &mut sink, progress_callback),
+            FormatKind::JsonLines | FormatKind::Csv | FormatKind::Tsv => bail!("{:?} files are not formatted as a whole", kind),
+        };
+        let error = match result {
+            Ok(()) => None,
+            Err(e) => Some(e.downcast::<SyntaxError>()?),
+        };
+        
+        sink.finish()?;
+        Ok(error)
+    }
+    
+    /// Map the whole input for formatters that copy tokens straight from the source
+    fn map_input(input: &File, input_path: &Path) -> Result<Mmap> {
+        unsafe { Mmap::map(input) }
+            .with_context(|| format!("Failed to memory-map file: {}", input_path.display()))
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Destination for formatter output that also records where each output line came from.
+///
+/// Formatters call `set_source_offset` with the input byte offset of every token before
+/// writing it. The offset current when a line's first non-blank byte is written becomes
+/// that line's entry in the line map, so entry `n` of the map is the input offset of the
+/// token output line `n` starts with. Lines with no content take the offset current at
+/// their end. The map is a flat array of little-endian `u64`s, read back by `LineMap`.
+pub struct FormatSink<'a> {
+    output: BufWriter<Box<dyn Write + 'a>>,
+    line_map: BufWriter<Box<dyn Write + 'a>>,
+    source_offset: u64,
+    line_pending: bool,
+    /// Nothing has been written yet
+    empty: bool,
+    /// Fails every write once set, which stops whichever formatter is writing
+    cancel: Option<&'a AtomicBool>,
+}
+
+impl<'a> FormatSink<'a> {
+    pub fn new(output: impl Write + 'a, line_map: impl Write + 'a) -> io::Result<Self> {
+        Ok(Self {
+            output: BufWriter::with_capacity(64 * 1024, Box::new(output)),
+            line_map: BufWriter::with_capacity(64 * 1024, Box::new(line_map)),
+            source_offset: 0,
+            line_pending: true,
+            empty: true,
+            cancel: None,
+        })
+    }
+    
+    fn record_line(&mut self) -> io::Result<()> {
+        self.line_pending = false;
+        self.line_map.write_all(&self.source_offset.to_le_bytes())
+    }
+    
+    pub fn set_cancel(&mut self, cancel: &'a AtomicBool) {
+        self.cancel = Some(cancel);
+    }
+    
+    /// Fail once cancelled, for formatters to call before work that doesn't write anything
+    pub fn check_cancel(&self) -> io::Result<()> {
+        // Not `Interrupted`, which `write_all` would retry forever
+        if self.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            return Err(io::Error::other("Formatting cancelled"));
+        }
+        Ok(())
+    }
+    
+    pub fn set_source_offset(&mut self, offset: u64) {
+        self.source_offset = offset;
+    }
+    
+    /// Begin a new output line indented `depth` times, for the token at input offset `offset`.
+    /// Nothing is written before the first token but the indentation.
+    pub fn start_line(&mut self, depth: usize, indent: &[u8], offset: u64) -> io::Result<()> {
+        self.set_source_offset(offset);
+        if !self.empty {
+            self.write_all(b"\n")?;
+        }
+        for _ in 0..depth {
+            self.write_all(indent)?;
+        }
+        Ok(())
+    }
+    
+    /// Copy source bytes that start at input offset `offset`, mapping each of their lines to
+    /// where it came from. Line breaks are written as `\n`.
+    pub fn write_verbatim(&mut self, raw: &[u8], offset: u64) -> io::Result<()> {
+        let mut line_offset = offset;
+        let mut lines = raw.split(|&byte| byte == b'\n').peekable();
+        while let Some(line) = lines.next() {
+            self.set_source_offset(line_offset);
+            line_offset += line.len() as u64 + 1;
+            if lines.peek().is_some() {
+                self.write_all(line.strip_suffix(b"\r").unwrap_or(line))?;
+                self.write_all(b"\n")?;
+            } else {
+                self.write_all(line)?;
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.line_pending {
+            self.record_line()?;
+        }
+        self.output.flush()?;
+        self.line_map.flush()
+    }
+}
+
+impl Write for FormatSink<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_cancel()?;
+        let written = self.output.write(buf)?;
+        self.empty &= written == 0;
+        for &byte in &buf[..written] {
+            match byte {
+                b'\n' => {
+                    if self.line_pending {
+                        self.record_line()?;
+                    }
+                    self.line_pending = true;
+                }
+                b' ' | b'\t' | b'\r' => {}
+                _ if self.line_pending => self.record_line()?,
+                _ => {}
+            }
+        }
+        Ok(written)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()?;
+        self.line_map.flush()
+    }
+}
@@ -0,0 +1,90 @@
+use super::anchor::SourceAnchors;
+use super::sink::FormatSink;
+use super::SyntaxError;
+use crate::file_reader::ProgressCallback;
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+
+/// TOML formatter and validator.
+///
+/// The document is parsed whole and printed again in a normalised layout, with tables and
+/// keys in their original order. Comments are not kept.
+pub struct TomlFormatter;
+
+impl TomlFormatter {
+    pub fn format<R: Read>(mut input: R, output: &mut FormatSink, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        let mut text = String::new();
+        input.read_to_string(&mut text).context("TOML input is not valid UTF-8")?;
+        output.check_cancel()?;
+        
+        let table: ::toml::Table = ::toml::from_str(&text).map_err(|error| Self::error(&text, error))?;
+        let formatted = ::toml::to_string_pretty(&table)?;
+        
+        let mut anchors = SourceAnchors::new(&text);
+        for line in formatted.lines() {
+            output.set_source_offset(anchors.locate(Self::key(line)));
+            writeln!(output, "{}", line)?;
+        }
+        
+        if let Some(callback) = progress_callback {
+            callback(1.0, "Formatting TOML...");
+        }
+        Ok(())
+    }
+    
+    /// The table header or key a formatted line starts with, if any
+    fn key(line: &str) -> Option<&str> {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            return Some(line);
+        }
+        line.find(" = ").map(|end| &line[..end])
+    }
+    
+    fn error(text: &str, error: ::toml::de::Error) -> anyhow::Error {
+        let offset = error.span().map_or(0, |span| span.start).min(text.len());
+        let before = &text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |line| line.chars().count()) + 1;
+        
+        SyntaxError {
+            offset: offset as u64,
+            message: format!("Failed to parse TOML: {} at line {} column {}", error.message().trim_end(), line, column),
+        }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn format(input: &str) -> (Result<()>, String, Vec<u64>) {
+        let mut output = Vec::new();
+        let mut line_map = Vec::new();
+        let mut sink = FormatSink::new(&mut output, &mut line_map).unwrap();
+        let result = TomlFormatter::format(input.as_bytes(), &mut sink, None);
+        sink.finish().unwrap();
+        
+        let offsets = line_map.chunks(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).collect();
+        (result, String::from_utf8(output).unwrap(), offsets)
+    }
+    
+    #[test]
+    fn test_format() {
+        let input = "title   =  \"x\"\n\n[server]\n  port=80\n  hosts = [ \"a\",\"b\" ]\n";
+        let (result, output, offsets) = format(input);
+        assert!(result.is_ok());
+        assert_eq!(output.lines().next(), Some("title = \"x\""));
+        assert!(output.contains("[server]\nport = 80\n"), "{}", output);
+        
+        let server_line = output.lines().position(|line| line == "[server]").unwrap();
+        assert_eq!(offsets[server_line], 16);
+    }
+    
+    #[test]
+    fn test_format_error() {
+        let (result, _, _) = format("a = 1\nb = \n");
+        let error = result.unwrap_err().downcast::<SyntaxError>().unwrap();
+        assert!(error.message.contains("line 2"), "{}", error);
+    }
+}
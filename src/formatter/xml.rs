@@ -0,0 +1,218 @@
+use super::sink::FormatSink;
+use super::SyntaxError;
+use crate::file_reader::ProgressCallback;
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+
+const INDENT: &[u8] = b"    ";
+
+/// Re-indenting XML formatter.
+///
+/// Every node is copied through byte for byte from the source, so comments, processing
+/// instructions, CDATA, entities and attribute layout come out exactly as they went in. The
+/// only change is to whitespace-only text between elements, which is replaced by a line
+/// break and indentation. Inside elements with `xml:space="preserve"`, and inside elements
+/// that have text of their own, all whitespace is kept and nothing is re-indented.
+/// Whitespace that runs into text, or that separates two elements without a line break, as
+/// in `<em>A</em> <em>B</em>`, counts as text. Whether an element has text is only known once
+/// the text is reached, so whitespace before its first child element is still re-indented.
+pub struct XmlFormatter;
+
+/// Formatting state of an open element
+#[derive(Debug, Clone, Copy, Default)]
+struct OpenElement {
+    preserve_space: bool,
+    has_text: bool,
+    has_children: bool,
+}
+
+impl OpenElement {
+    /// Whether the element's content is copied as it is, without adding line breaks
+    fn is_inline(&self) -> bool {
+        self.preserve_space || self.has_text
+    }
+}
+
+impl XmlFormatter {
+    pub fn format(input: &[u8], output: &mut FormatSink, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        let mut reader = Reader::from_reader(input);
+        let mut open: Vec<OpenElement> = Vec::new();
+        let total_bytes = input.len() as u64;
+        let progress_interval = (total_bytes / 20).max(1); // Update every 5%
+        let mut last_progress_pos = 0;
+        // Whitespace-only text waits for the next event to tell whether it is indentation
+        let mut pending_space: Option<(usize, usize)> = None;
+        let mut after_element = false;
+        
+        loop {
+            let start = reader.buffer_position();
+            let event = match reader.read_event() {
+                Ok(Event::Eof) => break,
+                Ok(event) => event,
+                Err(e) => {
+                    let offset = reader.buffer_position() as u64;
+                    return Err(SyntaxError { offset, message: format!("XML parsing error: {} at byte {}", e, offset) }.into());
+                }
+            };
+            let end = reader.buffer_position();
+            let raw = &input[start..end];
+            
+            if let Some((space_start, space_end)) = pending_space.take() {
+                let space = &input[space_start..space_end];
+                let significant = match event {
+                    Event::Text(_) | Event::CData(_) => true,
+                    Event::End(_) => false,
+                    _ => after_element && !space.contains(&b'\n'),
+                };
+                if let Some(parent) = open.last_mut().filter(|_| significant) {
+                    parent.has_text = true;
+                    output.write_verbatim(space, space_start as u64)?;
+                }
+            }
+            let inline = open.last().is_some_and(OpenElement::is_inline);
+            let closes_element = matches!(event, Event::End(_) | Event::Empty(_));
+            
+            match event {
+                Event::Text(_) if raw.iter().all(u8::is_ascii_whitespace) => {
+                    // Indentation between elements takes the place of whitespace-only text
+                    if inline {
+                        output.write_verbatim(raw, start as u64)?;
+                    } else {
+                        pending_space = Some((start, end));
+                    }
+                }
+                Event::Text(_) | Event::CData(_) => {
+                    if let Some(parent) = open.last_mut() {
+                        parent.has_text = true;
+                        parent.has_children = true;
+                    }
+                    output.write_verbatim(raw, start as u64)?;
+                }
+                Event::End(_) => {
+                    // Elements with no content, such as `<a></a>`, are closed on the same line
+                    let element = open.pop().unwrap_or_default();
+                    if !element.is_inline() && element.has_children {
+                        output.start_line(open.len(), INDENT, start as u64)?;
+                    }
+                    output.write_verbatim(raw, start as u64)?;
+                }
+                event => {
+                    if let Some(parent) = open.last_mut() {
+                        parent.has_children = true;
+                    }
+                    if !inline {
+                        output.start_line(open.len(), INDENT, start as u64)?;
+                    }
+                    output.write_verbatim(raw, start as u64)?;
+                    
+                    if let Event::Start(tag) = event {
+                        let inherited = open.last().is_some_and(|parent| parent.preserve_space);
+                        open.push(OpenElement {
+                            preserve_space: Self::space_attribute(&tag).unwrap_or(inherited),
+                            ..OpenElement::default()
+                        });
+                    }
+                }
+            }
+            if pending_space.is_none() {
+                after_element = closes_element;
+            }
+            
+            if let Some(callback) = progress_callback {
+                if start as u64 > last_progress_pos + progress_interval {
+                    callback(start as f64 / total_bytes as f64, "Formatting XML...");
+                    last_progress_pos = start as u64;
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// `Some(true)` for `xml:space="preserve"`, `Some(false)` for `xml:space="default"`
+    fn space_attribute(tag: &BytesStart) -> Option<bool> {
+        match tag.try_get_attribute("xml:space") {
+            Ok(Some(attribute)) => match attribute.value.as_ref() {
+                b"preserve" => Some(true),
+                b"default" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn format_with_map(input: &str) -> Result<(String, Vec<u64>)> {
+        let mut output = Vec::new();
+        let mut line_map = Vec::new();
+        let mut sink = FormatSink::new(&mut output, &mut line_map)?;
+        XmlFormatter::format(input.as_bytes(), &mut sink, None)?;
+        sink.finish()?;
+        
+        let offsets = line_map
+            .chunks(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok((String::from_utf8(output).unwrap(), offsets))
+    }
+    
+    fn format(input: &str) -> String {
+        format_with_map(input).unwrap().0
+    }
+    
+    #[test]
+    fn test_reindents_between_elements() {
+        let input = "<?xml version=\"1.0\"?>\n<root>  <a x='1'>text</a><b>\n\n<c/></b><d></d></root>";
+        assert_eq!(
+            format(input),
+            "<?xml version=\"1.0\"?>\n<root>\n    <a x='1'>text</a>\n    <b>\n        <c/>\n    </b>\n    <d></d>\n</root>"
+        );
+        
+        let (_, offsets) = format_with_map(input).unwrap();
+        assert_eq!(offsets, vec![0, 22, 30, 47, 52, 56, 60, 67]);
+    }
+    
+    #[test]
+    fn test_keeps_content_verbatim() {
+        // Comments, CDATA, entities and the whitespace around text are left alone
+        let input = "<r><!--  a\n   comment --><p>  Hello <b>big</b> <i>world</i>\n</p><s><![CDATA[ x < y\n  ]]></s><t>&amp; &#60;</t></r>";
+        assert_eq!(
+            format(input),
+            "<r>\n    <!--  a\n   comment -->\n    <p>  Hello <b>big</b> <i>world</i>\n</p>\n    <s><![CDATA[ x < y\n  ]]></s>\n    <t>&amp; &#60;</t>\n</r>"
+        );
+    }
+    
+    #[test]
+    fn test_keeps_significant_whitespace() {
+        // A space between inline elements, or running into text, is part of the text content
+        assert_eq!(format("<r><para><em>A</em> <em>B</em></para></r>"), "<r>\n    <para>\n        <em>A</em> <em>B</em></para>\n</r>");
+        assert_eq!(format("<r><p><b>x</b>\n  tail</p></r>"), "<r>\n    <p>\n        <b>x</b>\n  tail</p>\n</r>");
+        // Line breaks between elements are still indentation
+        assert_eq!(format("<r><a/>\n  <b/> </r>"), "<r>\n    <a/>\n    <b/>\n</r>");
+    }
+    
+    #[test]
+    fn test_preserves_space() {
+        let input = "<r><pre xml:space=\"preserve\">\n  <a> </a>\n  <b/>\n</pre><c> <d/> </c></r>";
+        assert_eq!(
+            format(input),
+            "<r>\n    <pre xml:space=\"preserve\">\n  <a> </a>\n  <b/>\n</pre>\n    <c>\n        <d/>\n    </c>\n</r>"
+        );
+    }
+    
+    #[test]
+    fn test_error_keeps_output_so_far() {
+        let mut output = Vec::new();
+        let mut sink = FormatSink::new(&mut output, std::io::sink()).unwrap();
+        let error = XmlFormatter::format(b"<a><b></a>", &mut sink, None).unwrap_err();
+        sink.finish().unwrap();
+        
+        assert!(error.downcast_ref::<SyntaxError>().is_some());
+        assert_eq!(String::from_utf8(output).unwrap(), "<a>\n    <b>");
+    }
+}
@@ -0,0 +1,99 @@
+use super::anchor::SourceAnchors;
+use super::sink::FormatSink;
+use super::SyntaxError;
+use crate::file_reader::ProgressCallback;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{Read, Write};
+
+/// YAML formatter and validator.
+///
+/// Each document is parsed and printed again with two-space indentation, and every document
+/// is introduced by a numbered `---` separator so the boundaries of a multi-document bundle
+/// stand out. Documents are parsed one at a time but the input is read into memory whole.
+/// Comments are not kept and aliases are expanded.
+pub struct YamlFormatter;
+
+impl YamlFormatter {
+    pub fn format<R: Read>(mut input: R, output: &mut FormatSink, progress_callback: Option<&ProgressCallback>) -> Result<()> {
+        let mut text = String::new();
+        input.read_to_string(&mut text).context("YAML input is not valid UTF-8")?;
+        output.check_cancel()?;
+        
+        let mut anchors = SourceAnchors::new(&text);
+        for (index, document) in serde_yaml::Deserializer::from_str(&text).enumerate() {
+            let value = serde_yaml::Value::deserialize(document).map_err(Self::error)?;
+            
+            // The first document may not have a marker, later ones always do
+            let separator_offset = anchors.locate((index > 0).then_some("---"));
+            output.set_source_offset(separator_offset);
+            writeln!(output, "--- # document {}", index + 1)?;
+            
+            let formatted = serde_yaml::to_string(&value)?;
+            for line in formatted.lines() {
+                output.set_source_offset(anchors.locate(Self::key(line)));
+                writeln!(output, "{}", line)?;
+            }
+            
+            if let Some(callback) = progress_callback {
+                callback(anchors.position() as f64 / text.len().max(1) as f64, "Formatting YAML...");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// The mapping key a formatted line starts with, if any
+    fn key(line: &str) -> Option<&str> {
+        let entry = line.trim_start().trim_start_matches("- ");
+        let end = entry.find(": ").or_else(|| entry.ends_with(':').then(|| entry.len() - 1))?;
+        Some(&entry[..end])
+    }
+    
+    fn error(error: serde_yaml::Error) -> anyhow::Error {
+        SyntaxError {
+            offset: error.location().map_or(0, |location| location.index() as u64),
+            message: format!("Failed to parse YAML: {}", error),
+        }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn format(input: &str) -> (Result<()>, String, Vec<u64>) {
+        let mut output = Vec::new();
+        let mut line_map = Vec::new();
+        let mut sink = FormatSink::new(&mut output, &mut line_map).unwrap();
+        let result = YamlFormatter::format(input.as_bytes(), &mut sink, None);
+        sink.finish().unwrap();
+        
+        let offsets = line_map.chunks(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).collect();
+        (result, String::from_utf8(output).unwrap(), offsets)
+    }
+    
+    #[test]
+    fn test_format_documents() {
+        let input = "kind: A\nspec:\n    replicas:    2\n---\n# second\nkind: B\nitems:\n-   x\n";
+        let (result, output, offsets) = format(input);
+        assert!(result.is_ok());
+        assert_eq!(output, "--- # document 1\nkind: A\nspec:\n  replicas: 2\n--- # document 2\nkind: B\nitems:\n- x\n");
+        
+        // Lines map back to their keys, and the second separator to the second marker. The
+        // empty line after the final newline continues the last one.
+        assert_eq!(offsets, vec![0, 0, 8, 18, 33, 46, 54, 54, 54]);
+    }
+    
+    #[test]
+    fn test_format_error() {
+        let input = "a: 1\n---\nb: [1, 2\nc: 3\n";
+        let (result, output, _) = format(input);
+        let error = result.unwrap_err().downcast::<SyntaxError>().unwrap();
+        assert!(error.message.contains("line 4"), "{}", error);
+        assert!(error.offset > 9);
+        
+        // Documents before the error are kept
+        assert_eq!(output, "--- # document 1\na: 1\n");
+    }
+}
@@ -0,0 +1,86 @@
+use crate::file_reader::FileReader;
+use anyhow::{bail, Context, Result};
+
+/// A location typed into the goto prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GotoTarget {
+    /// 1-based line number, e.g. `48213977`
+    Line(usize),
+    /// Position relative to the file length, e.g. `50%`
+    Percent(f64),
+    /// Byte offset into the file, e.g. `@123456789` or `@0x75bcd15`
+    ByteOffset(usize),
+}
+
+impl GotoTarget {
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            bail!("Enter a line number, a percentage or an @offset");
+        }
+        
+        if let Some(offset) = input.strip_prefix('@') {
+            let offset = Self::strip_separators(offset);
+            let parsed = match offset.strip_prefix("0x").or_else(|| offset.strip_prefix("0X")) {
+                Some(hex) => usize::from_str_radix(hex, 16),
+                None => offset.parse(),
+            };
+            return parsed
+                .map(GotoTarget::ByteOffset)
+                .with_context(|| format!("Invalid byte offset: {}", input));
+        }
+        
+        if let Some(percent) = input.strip_suffix('%') {
+            let percent: f64 = percent.trim().parse()
+                .with_context(|| format!("Invalid percentage: {}", input))?;
+            if !(0.0..=100.0).contains(&percent) {
+                bail!("Percentage must be between 0 and 100: {}", input);
+            }
+            return Ok(GotoTarget::Percent(percent));
+        }
+        
+        Self::strip_separators(input)
+            .parse()
+            .map(GotoTarget::Line)
+            .with_context(|| format!("Invalid line number: {}", input))
+    }
+    
+    /// Resolve the target to a 0-based line index within the file
+    pub fn resolve(&self, file_reader: &FileReader) -> usize {
+        let last_line = file_reader.line_count().saturating_sub(1);
+        match *self {
+            GotoTarget::Line(line) => line.saturating_sub(1).min(last_line),
+            GotoTarget::Percent(percent) => ((last_line as f64) * percent / 100.0).round() as usize,
+            GotoTarget::ByteOffset(offset) => file_reader.line_for_offset(offset),
+        }
+    }
+    
+    /// Allow digit grouping such as `48_213_977` or `48,213,977`
+    fn strip_separators(input: &str) -> String {
+        input.chars().filter(|&c| c != '_' && c != ',').collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_goto_target() {
+        assert_eq!(GotoTarget::parse("48213977").unwrap(), GotoTarget::Line(48213977));
+        assert_eq!(GotoTarget::parse(" 48,213,977 ").unwrap(), GotoTarget::Line(48213977));
+        assert_eq!(GotoTarget::parse("50%").unwrap(), GotoTarget::Percent(50.0));
+        assert_eq!(GotoTarget::parse("12.5%").unwrap(), GotoTarget::Percent(12.5));
+        assert_eq!(GotoTarget::parse("@123456789").unwrap(), GotoTarget::ByteOffset(123456789));
+        assert_eq!(GotoTarget::parse("@0xff").unwrap(), GotoTarget::ByteOffset(255));
+    }
+    
+    #[test]
+    fn test_parse_goto_target_errors() {
+        assert!(GotoTarget::parse("").is_err());
+        assert!(GotoTarget::parse("abc").is_err());
+        assert!(GotoTarget::parse("150%").is_err());
+        assert!(GotoTarget::parse("@").is_err());
+        assert!(GotoTarget::parse("-3").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::file_reader::{FileReader, SearchMatch};
+use crate::structure::{BlockKind, Node, StructureIndex};
+use anyhow::{bail, Result};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// One step of a JSONPath expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    /// `.name` or `['name']`
+    Member(String),
+    /// `[3]`, or `[-1]` counting from the end
+    Index(i64),
+    /// `.*` or `[*]`
+    Wildcard,
+    /// `..` followed by another selector, applied at every level below the current one
+    Descendants(Box<Selector>),
+}
+
+/// A JSONPath expression supporting member, index and wildcard selectors and `..` descent,
+/// evaluated against the line structure of a pretty-printed document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    selectors: Vec<Selector>,
+}
+
+impl JsonPath {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = expression.trim();
+        let Some(mut rest) = expression.strip_prefix('$') else {
+            bail!("JSONPath must start with $");
+        };
+        
+        let mut selectors = Vec::new();
+        while !rest.is_empty() {
+            let (descend, after_dots) = match rest.strip_prefix("..") {
+                Some(after) => (true, after),
+                None => (false, rest),
+            };
+            
+            let (selector, remaining) = if let Some(after) = after_dots.strip_prefix('[') {
+                Self::parse_bracket(after)?
+            } else {
+                let name_part = if descend { after_dots } else {
+                    match after_dots.strip_prefix('.') {
+                        Some(after) => after,
+                        None => bail!("Expected . or [ at '{}'", after_dots),
+                    }
+                };
+                let end = name_part.find(['.', '[']).unwrap_or(name_part.len());
+                let name = &name_part[..end];
+                let selector = match name {
+                    "" => bail!("Expected a member name at '{}'", name_part),
+                    "*" => Selector::Wildcard,
+                    _ => Selector::Member(name.to_string()),
+                };
+                (selector, &name_part[end..])
+            };
+            
+            selectors.push(if descend { Selector::Descendants(Box::new(selector)) } else { selector });
+            rest = remaining;
+        }
+        
+        Ok(Self { selectors })
+    }
+    
+    /// Parse the inside of `[...]`, returning the selector and the text after the `]`
+    fn parse_bracket(text: &str) -> Result<(Selector, &str)> {
+        if let Some(quote) = text.chars().next().filter(|&c| c == '\'' || c == '"') {
+            let body = &text[1..];
+            let Some(end) = body.find(quote) else {
+                bail!("Unterminated quoted name");
+            };
+            let Some(rest) = body[end + 1..].strip_prefix(']') else {
+                bail!("Expected ] after quoted name");
+            };
+            return Ok((Selector::Member(body[..end].to_string()), rest));
+        }
+        
+        let Some(end) = text.find(']') else {
+            bail!("Missing ]");
+        };
+        let inner = text[..end].trim();
+        let selector = match inner {
+            "*" => Selector::Wildcard,
+            _ => match inner.parse() {
+                Ok(index) => Selector::Index(index),
+                Err(_) => bail!("Unsupported selector [{}]: use a name, an index or *", inner),
+            },
+        };
+        Ok((selector, &text[end + 1..]))
+    }
+    
+    /// Every node the expression selects, as matches spanning the start of each node's line.
+    /// Returns `None` if cancelled part way.
+    pub fn evaluate(&self, structure: &StructureIndex, reader: &FileReader, cancel: &AtomicBool) -> Option<Vec<SearchMatch>> {
+        let mut nodes: Vec<Node> = structure.root().into_iter().collect();
+        
+        for selector in &self.selectors {
+            let mut selected = Vec::new();
+            for &node in &nodes {
+                if cancel.load(Ordering::Relaxed) {
+                    return None;
+                }
+                Self::select(selector, node, structure, reader, &mut selected);
+            }
+            
+            // Descent can reach the same node along several routes
+            selected.sort_by_key(|&node| structure.start_line(node));
+            selected.dedup();
+            nodes = selected;
+        }
+        
+        let matches = nodes
+            .into_iter()
+            .map(|node| {
+                let line = structure.start_line(node);
+                let text = reader.get_line(line).unwrap_or("");
+                let start = text.chars().take_while(|c| c.is_whitespace()).count();
+                let end = start + text.trim().trim_end_matches(',').chars().count();
+                SearchMatch { line, start, end }
+            })
+            .collect();
+        Some(matches)
+    }
+    
+    fn select(selector: &Selector, node: Node, structure: &StructureIndex, reader: &FileReader, selected: &mut Vec<Node>) {
+        let Node::Block(block) = node else {
+            // Values written on one line have no children to select
+            return;
+        };
+        
+        match selector {
+            Selector::Member(name) => {
+                if structure.block(block).kind == BlockKind::Object {
+                    selected.extend(structure.children(block).filter(|&child| {
+                        Self::member_name(reader.get_line(structure.start_line(child)).unwrap_or("")).as_deref() == Some(name.as_str())
+                    }));
+                }
+            }
+            Selector::Index(index) => {
+                if structure.block(block).kind == BlockKind::Array {
+                    let child = if *index >= 0 {
+                        structure.children(block).nth(*index as usize)
+                    } else {
+                        let count = structure.children(block).count();
+                        count.checked_sub(index.unsigned_abs() as usize).and_then(|index| structure.children(block).nth(index))
+                    };
+                    selected.extend(child);
+                }
+            }
+            Selector::Wildcard => selected.extend(structure.children(block)),
+            Selector::Descendants(selector) => {
+                for descendant in structure.descendant_blocks(block) {
+                    Self::select(selector, Node::Block(descendant), structure, reader, selected);
+                }
+            }
+        }
+    }
+    
+    /// The member name a line of a pretty-printed object starts with, unescaped
+    fn member_name(line: &str) -> Option<String> {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('"') {
+            return None;
+        }
+        
+        // Find the closing quote, stepping over escapes
+        let mut escaped = false;
+        let end = trimmed[1..].char_indices().find_map(|(i, c)| {
+            let found = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            found.then_some(i + 1)
+        })?;
+        
+        if !trimmed[end + 1..].trim_start().starts_with(':') {
+            return None;
+        }
+        serde_json::from_str(&trimmed[..=end]).ok()
+    }
+    
+    /// The normalized path of the value a line belongs to, such as `$.items[3].name`
+    pub fn path_of_line(structure: &StructureIndex, reader: &FileReader, line: usize) -> String {
+        let mut segments = Vec::new();
+        let mut current = structure.node_at(line);
+        
+        while let Some((node, Some(parent))) = current {
+            let segment = match structure.block(parent).kind {
+                BlockKind::Array => Selector::Index(structure.index_in_parent(node, parent) as i64),
+                _ => match Self::member_name(reader.get_line(structure.start_line(node)).unwrap_or("")) {
+                    Some(name) => Selector::Member(name),
+                    None => Selector::Wildcard,
+                },
+            };
+            segments.push(segment);
+            current = Some((Node::Block(parent), structure.block(parent).parent));
+        }
+        
+        let mut path = String::from("$");
+        for segment in segments.iter().rev() {
+            match segment {
+                Selector::Member(name) if Self::is_plain_name(name) => {
+                    let _ = write!(path, ".{}", name);
+                }
+                Selector::Member(name) => {
+                    let _ = write!(path, "['{}']", name.replace('\'', "\\'"));
+                }
+                Selector::Index(index) => {
+                    let _ = write!(path, "[{}]", index);
+                }
+                _ => path.push_str("[*]"),
+            }
+        }
+        path
+    }
+    
+    fn is_plain_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::TempFile;
+    use crate::syntax::SyntaxKind;
+    
+    const DOCUMENT: &str = "{\n  \"items\": [\n    {\n      \"name\": \"a\",\n      \"tags\": [\n        \"x\"\n      ]\n    },\n    {\n      \"name\": \"b\"\n    }\n  ],\n  \"odd key\": 1\n}";
+    
+    fn document() -> (FileReader, StructureIndex) {
+        let reader = TempFile::new(DOCUMENT).reader();
+        let structure = StructureIndex::build(&reader, SyntaxKind::Json, &AtomicBool::new(false)).unwrap();
+        (reader, structure)
+    }
+    
+    fn lines_for(expression: &str) -> Vec<usize> {
+        let (reader, structure) = document();
+        let matches = JsonPath::parse(expression).unwrap().evaluate(&structure, &reader, &AtomicBool::new(false)).unwrap();
+        matches.into_iter().map(|m| m.line).collect()
+    }
+    
+    #[test]
+    fn test_parse() {
+        assert_eq!(JsonPath::parse("$.items[-1]..name['x']").unwrap().selectors, vec![
+            Selector::Member("items".to_string()),
+            Selector::Index(-1),
+            Selector::Descendants(Box::new(Selector::Member("name".to_string()))),
+            Selector::Member("x".to_string()),
+        ]);
+        assert!(JsonPath::parse("items").is_err());
+        assert!(JsonPath::parse("$.items[?(@.a)]").is_err());
+        assert!(JsonPath::parse("$['a").is_err());
+    }
+    
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(lines_for("$"), vec![0]);
+        assert_eq!(lines_for("$.items"), vec![1]);
+        assert_eq!(lines_for("$.items[1].name"), vec![9]);
+        assert_eq!(lines_for("$.items[-1]"), vec![8]);
+        assert_eq!(lines_for("$.items[*].name"), vec![3, 9]);
+        assert_eq!(lines_for("$..name"), vec![3, 9]);
+        assert_eq!(lines_for("$..tags[0]"), vec![5]);
+        assert_eq!(lines_for("$['odd key']"), vec![12]);
+        assert!(lines_for("$.missing").is_empty());
+    }
+    
+    #[test]
+    fn test_path_of_line() {
+        let (reader, structure) = document();
+        let path_of = |line| JsonPath::path_of_line(&structure, &reader, line);
+        assert_eq!(path_of(0), "$");
+        assert_eq!(path_of(3), "$.items[0].name");
+        assert_eq!(path_of(5), "$.items[0].tags[0]");
+        assert_eq!(path_of(7), "$.items[0]");
+        assert_eq!(path_of(9), "$.items[1].name");
+        assert_eq!(path_of(12), "$['odd key']");
+    }
+}
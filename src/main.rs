@@ -5,23 +5,71 @@ mod selection;
 mod constants;
 mod viewer;
 mod formatter;
+mod app_event;
+mod goto;
+mod bookmarks;
+mod expansion;
+mod syntax;
+mod structure;
+mod jsonpath;
+mod xpath;
+mod query;
+mod table;
+mod clipboard_backend;
+mod context_menu;
+mod viewer_stack;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use file_reader::{FileReader, ProgressCallback};
-use formatter::FileFormatter;
+use app_event::AppEvent;
+use clipboard_backend::ClipboardBackend;
+use constants::Constants;
+use file_reader::FileReader;
+use formatter::{FormatDetector, FormatKind};
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
 use viewer::{Viewer, ViewerAction};
-use event_handler::EventHandler;
+use viewer_stack::ViewerStack;
+
+/// How to choose the formatter for the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FormatChoice {
+    /// Detect the format from the file's content
+    Auto,
+    Json,
+    /// JSON Lines, one record per line
+    Jsonl,
+    Xml,
+    Yaml,
+    Toml,
+    Csv,
+    Tsv,
+    Html,
+    /// JavaScript
+    Js,
+    Css,
+    /// Always show the file as it is
+    None,
+}
+
+/// Where copied text goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ClipboardChoice {
+    /// `BIGVIEW_CLIPBOARD` if set, otherwise the terminal over SSH or without a display
+    Auto,
+    /// The local X11 or OS clipboard
+    System,
+    /// The terminal's clipboard, through an OSC 52 escape sequence
+    Osc52,
+}
 
 #[derive(Parser)]
 #[command(name = "bigview")]
@@ -29,18 +77,52 @@ use event_handler::EventHandler;
 struct Args {
     /// Path to the file to view
     file_path: String,
+    
+    /// Write the formatted copy of a structured file here instead of the cache directory
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    
+    /// Format to pretty-print the file as
+    #[arg(long, value_enum, default_value_t = FormatChoice::Auto)]
+    format: FormatChoice,
+    
+    /// Clipboard to copy selections to
+    #[arg(long, value_enum, default_value_t = ClipboardChoice::Auto)]
+    clipboard: ClipboardChoice,
+    
+    /// Characters besides letters, digits and `_` that double-click selects as part of a word.
+    /// Defaults to `BIGVIEW_WORD_CHARS`, or `-.:` if that isn't set.
+    #[arg(long, value_name = "CHARS")]
+    word_chars: Option<String>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
     
-    // Format file if needed (JSON/XML)
-    let file_path = FileFormatter::format_if_needed(&args.file_path)?;
+    let format_kind = match args.format {
+        FormatChoice::Auto => FormatDetector::detect(Path::new(&args.file_path))?,
+        FormatChoice::Json => Some(FormatKind::Json),
+        FormatChoice::Jsonl => Some(FormatKind::JsonLines),
+        FormatChoice::Xml => Some(FormatKind::Xml),
+        FormatChoice::Yaml => Some(FormatKind::Yaml),
+        FormatChoice::Toml => Some(FormatKind::Toml),
+        FormatChoice::Csv => Some(FormatKind::Csv),
+        FormatChoice::Tsv => Some(FormatKind::Tsv),
+        FormatChoice::Html => Some(FormatKind::Html),
+        FormatChoice::Js => Some(FormatKind::JavaScript),
+        FormatChoice::Css => Some(FormatKind::Css),
+        FormatChoice::None => None,
+    };
+    let clipboard = match args.clipboard {
+        ClipboardChoice::Auto => ClipboardBackend::detect(),
+        ClipboardChoice::System => ClipboardBackend::System,
+        ClipboardChoice::Osc52 => ClipboardBackend::Osc52,
+    };
     
     // Try to setup terminal, but if it fails, just load the file without UI
     let terminal_setup = enable_raw_mode().and_then(|_| {
         let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
         let backend = CrosstermBackend::new(stdout);
         Terminal::new(backend)
     });
@@ -48,14 +130,19 @@ fn main() -> Result<()> {
     match terminal_setup {
         Ok(mut terminal) => {
             // Run with UI
-            let result = load_file_with_progress(&file_path, &mut terminal);
+            let word_chars = args.word_chars
+                .or_else(|| std::env::var("BIGVIEW_WORD_CHARS").ok())
+                .unwrap_or_else(|| Constants::DEFAULT_WORD_CHARS.to_string());
+            let format_sniffed = args.format == FormatChoice::Auto;
+            let result = run_app(&args.file_path, format_kind, format_sniffed, args.output, clipboard, word_chars, &mut terminal);
             
             // Restore terminal
             disable_raw_mode()?;
             execute!(
                 terminal.backend_mut(),
                 LeaveAlternateScreen,
-                DisableMouseCapture
+                DisableMouseCapture,
+                DisableBracketedPaste
             )?;
             terminal.show_cursor()?;
             
@@ -71,101 +158,77 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn load_file_with_progress(file_path: &str, terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>) -> Result<()> {
-    // For large files, show progress. For small files, load directly.
-    let file_size = std::fs::metadata(file_path)
-        .map(|metadata| metadata.len())
-        .unwrap_or(0);
+fn run_app(file_path: &str, format_kind: Option<FormatKind>, format_sniffed: bool, format_output: Option<PathBuf>, clipboard: ClipboardBackend, word_chars: String, terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>) -> Result<()> {
+    let (event_tx, event_rx) = mpsc::channel();
+    app_event::spawn_input_reader(event_tx.clone());
     
-    // Show progress bar only for files larger than 10MB
-    if file_size > 10 * 1024 * 1024 {
-        // Create a channel for progress updates
-        let (progress_tx, progress_rx) = mpsc::channel();
-        let file_path_clone = file_path.to_string();
-        
-        // Create a minimal viewer to show progress (no file loaded yet)
-        let mut progress_viewer = Viewer::new_empty();
-        progress_viewer.show_progress(0.0, "Loading file...");
+    // Start with an empty viewer and load the file in the background
+    let mut viewer = Viewer::new_empty(event_tx.clone());
+    // Logs and prose can pass for CSV, so only a format given with --format opens in the table
+    viewer.set_format_kind(format_kind, !format_sniffed);
+    viewer.set_format_output(format_output);
+    viewer.set_clipboard(clipboard);
+    viewer.set_word_chars(word_chars);
+    match format_kind {
+        Some(FormatKind::JsonLines) => viewer.set_status_message("JSON Lines, press e to expand a record"),
+        Some(FormatKind::Csv) if format_sniffed => viewer.set_status_message("Looks like CSV, press t for the table view"),
+        Some(FormatKind::Tsv) if format_sniffed => viewer.set_status_message("Looks like TSV, press t for the table view"),
+        _ => {}
+    }
+    spawn_file_loader(file_path, &event_tx, &mut viewer);
+    
+    let mut viewers = ViewerStack::new(viewer);
+    let mut _file_watcher = None;
+    let mut needs_redraw = true;
+    
+    loop {
+        if needs_redraw {
+            terminal.draw(|f| viewers.shown().draw(f))?;
+            needs_redraw = false;
+        }
         
-        // Spawn file loading thread
-        let loading_thread = thread::spawn(move || {
-            let progress_callback: ProgressCallback = Box::new(move |progress, message| {
-                let _ = progress_tx.send((progress, message.to_string()));
-            });
+        // Block until something happens, then drain whatever else is queued before redrawing
+        let first_event = event_rx.recv()?;
+        for event in std::iter::once(first_event).chain(event_rx.try_iter()) {
+            needs_redraw |= event.affects_display();
             
-            FileReader::new_with_progress(&file_path_clone, Some(progress_callback))
-        });
-        
-        // Update progress bar while file is loading
-        let mut last_update = std::time::Instant::now();
-        loop {
-            // Check for progress updates
-            match progress_rx.try_recv() {
-                Ok((progress, message)) => {
-                    progress_viewer.show_progress(progress, &message);
-                    
-                    // Only update UI every 200ms to reduce overhead
-                    if last_update.elapsed() >= Duration::from_millis(200) {
-                        terminal.draw(|f| progress_viewer.draw(f))?;
-                        last_update = std::time::Instant::now();
-                    }
+            match event {
+                AppEvent::Loaded(result) => {
+                    let file_reader = result?;
+                    _file_watcher = app_event::watch_file(file_reader.path(), event_tx.clone()).ok();
+                    let viewer = viewers.shown();
+                    viewer.set_file_reader(file_reader);
                     
-                    if progress >= 1.0 {
-                        // Final update
-                        terminal.draw(|f| progress_viewer.draw(f))?;
-                        break;
+                    // Structured files open formatted, with the raw file shown until the copy is ready
+                    if format_kind.is_some_and(|kind| kind.has_formatted_view()) {
+                        viewer.toggle_formatted_view();
                     }
                 }
-                Err(mpsc::TryRecvError::Empty) => {
-                    // No progress update, continue
-                }
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    // Loading thread finished
-                    break;
+                event => {
+                    if let ViewerAction::Quit = viewers.handle_app_event(event) {
+                        return Ok(());
+                    }
                 }
             }
-            
-            // Longer delay to reduce CPU usage
-            thread::sleep(Duration::from_millis(100));
         }
-        
-        // Wait for loading thread to complete and get result
-        let file_reader = loading_thread.join().map_err(|_| anyhow::anyhow!("Loading thread panicked"))??;
-        
-        // Create the real viewer
-        let mut viewer = Viewer::new(file_reader);
-        
-        // Run the viewer
-        Ok(run_viewer(&mut viewer, terminal)?)
-    } else {
-        // Small file, load directly without progress bar
-        let file_reader = FileReader::new_with_progress(file_path, None)?;
-        let mut viewer = Viewer::new(file_reader);
-        
-        // Run the viewer
-        Ok(run_viewer(&mut viewer, terminal)?)
     }
 }
 
-fn run_viewer(viewer: &mut Viewer, terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>) -> io::Result<()> {
-    loop {
-        terminal.draw(|f| viewer.draw(f))?;
-        
-        // Check if a search was requested
-        if viewer.has_search_requested() {
-            if let Err(e) = viewer.perform_search_with_ui_progress(terminal) {
-                eprintln!("Search error: {}", e);
-            }
-        }
-        
-        // Check for events with a timeout to allow search processing
-        if crossterm::event::poll(Duration::from_millis(100))? {
-            let event = crossterm::event::read()?;
-            match EventHandler::handle_event(viewer, event) {
-                ViewerAction::Quit => break,
-                ViewerAction::None => continue,
-            }
-        }
+fn spawn_file_loader(file_path: &str, events: &mpsc::Sender<AppEvent>, viewer: &mut Viewer) {
+    // Show progress bar only for large files
+    let file_size = std::fs::metadata(file_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let show_progress = file_size > Constants::LOAD_PROGRESS_MIN_BYTES;
+    if show_progress {
+        viewer.show_progress(0.0, "Loading file...");
     }
-    Ok(())
+    let progress_callback = show_progress.then(|| app_event::progress_sender(events, None));
+    
+    let file_path = file_path.to_string();
+    let events = events.clone();
+    thread::spawn(move || {
+        let result = FileReader::new_with_progress(&file_path, progress_callback);
+        let _ = events.send(AppEvent::Loaded(result));
+    });
 }
@@ -0,0 +1,39 @@
+use crate::file_reader::{FileReader, SearchMatch};
+use crate::jsonpath::JsonPath;
+use crate::structure::StructureIndex;
+use crate::syntax::SyntaxKind;
+use crate::xpath::XPath;
+use anyhow::Result;
+use std::sync::atomic::AtomicBool;
+
+/// A structural query written in the path language of the document's syntax:
+/// JSONPath for JSON and XPath for XML
+pub enum DocumentQuery {
+    JsonPath(JsonPath),
+    XPath(XPath),
+}
+
+impl DocumentQuery {
+    pub fn parse(syntax: SyntaxKind, expression: &str) -> Result<Self> {
+        match syntax {
+            SyntaxKind::Json => JsonPath::parse(expression).map(DocumentQuery::JsonPath),
+            SyntaxKind::Xml => XPath::parse(expression).map(DocumentQuery::XPath),
+        }
+    }
+    
+    /// The lines the query selects. Returns `None` if cancelled part way.
+    pub fn evaluate(&self, structure: &StructureIndex, reader: &FileReader, cancel: &AtomicBool) -> Option<Vec<SearchMatch>> {
+        match self {
+            DocumentQuery::JsonPath(path) => path.evaluate(structure, reader, cancel),
+            DocumentQuery::XPath(path) => path.evaluate(structure, reader, cancel),
+        }
+    }
+    
+    /// The path of the value or element a line belongs to
+    pub fn path_of_line(syntax: SyntaxKind, structure: &StructureIndex, reader: &FileReader, line: usize) -> String {
+        match syntax {
+            SyntaxKind::Json => JsonPath::path_of_line(structure, reader, line),
+            SyntaxKind::Xml => XPath::path_of_line(structure, reader, line),
+        }
+    }
+}
@@ -1,5 +1,14 @@
-use clipboard::{ClipboardContext, ClipboardProvider};
 use crate::{file_reader::FileReader, text_utils::TextUtils};
+use std::io::{self, Write};
+
+/// Which text a keyboard selection covers between its anchor and the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisualMode {
+    /// From the anchor to the cursor, both characters included, as with `v` in vim
+    Char,
+    /// Every line from the anchor's to the cursor's, as with `V`
+    Line,
+}
 
 #[derive(Debug, Clone)]
 pub struct Selection {
@@ -19,6 +28,25 @@ impl Selection {
         }
     }
     
+    /// The selection made in a visual mode, given the anchor and cursor as (line, column)
+    pub fn visual(mode: VisualMode, anchor: (usize, usize), cursor: (usize, usize), file_reader: &FileReader) -> Self {
+        let (first, last) = if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) };
+        match mode {
+            VisualMode::Char => Self {
+                start_line: first.0,
+                start_col: first.1,
+                end_line: last.0,
+                end_col: last.1 + 1,
+            },
+            VisualMode::Line => Self {
+                start_line: first.0,
+                start_col: 0,
+                end_line: last.0,
+                end_col: file_reader.get_line(last.0).map_or(0, TextUtils::char_len),
+            },
+        }
+    }
+    
     pub fn update_end(&mut self, line: usize, col: usize) {
         self.end_line = line;
         self.end_col = col;
@@ -42,6 +70,41 @@ impl Selection {
         line >= start_line && line <= end_line
     }
     
+    /// The size of the selected text in bytes, worked out without copying it
+    pub fn byte_len(&self, file_reader: &FileReader) -> usize {
+        let (start_line, start_col, end_line, end_col) = self.normalize();
+        let offset = |line_num: usize, col: usize| {
+            file_reader.line_start(line_num) + Self::byte_offset(file_reader.get_line(line_num).unwrap_or(""), col)
+        };
+        offset(end_line, end_col).saturating_sub(offset(start_line, start_col))
+    }
+    
+    fn byte_offset(line: &str, col: usize) -> usize {
+        line.char_indices().nth(col).map_or(line.len(), |(byte, _)| byte)
+    }
+    
+    /// The selected part of each line, borrowed from the file
+    fn line_slices<'a>(&self, file_reader: &'a FileReader) -> impl Iterator<Item = &'a str> {
+        let (start_line, start_col, end_line, end_col) = self.normalize();
+        (start_line..=end_line).filter_map(move |line_num| {
+            let line = file_reader.get_line(line_num)?;
+            let start = if line_num == start_line { Self::byte_offset(line, start_col) } else { 0 };
+            let end = if line_num == end_line { Self::byte_offset(line, end_col) } else { line.len() };
+            Some(&line[start.min(end)..end])
+        })
+    }
+    
+    /// Write the selected text a line at a time, without building it in memory
+    pub fn write_text(&self, file_reader: &FileReader, output: &mut impl Write) -> io::Result<()> {
+        for (index, slice) in self.line_slices(file_reader).enumerate() {
+            if index > 0 {
+                output.write_all(b"\n")?;
+            }
+            output.write_all(slice.as_bytes())?;
+        }
+        Ok(())
+    }
+    
     pub fn get_text(&self, file_reader: &FileReader) -> Option<String> {
         let (start_line, start_col, end_line, end_col) = self.normalize();
         let mut result = String::new();
@@ -79,11 +142,39 @@ impl Selection {
         }
     }
     
-    pub fn copy_to_clipboard(&self, file_reader: &FileReader) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(text) = self.get_text(file_reader) {
-            let mut ctx = ClipboardContext::new()?;
-            ctx.set_contents(text)?;
-        }
-        Ok(())
+    /// The selected text with each line prefixed by its line number
+    pub fn get_numbered_text(&self, file_reader: &FileReader) -> Option<String> {
+        let (start_line, _, end_line, _) = self.normalize();
+        let width = (end_line + 1).to_string().len();
+        let text = self.get_text(file_reader)?;
+        let lines: Vec<String> = (start_line + 1..)
+            .zip(text.split('\n'))
+            .map(|(number, line)| format!("{:>width$}  {}", number, line, width = width))
+            .collect();
+        Some(lines.join("\n"))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::TempFile;
+    
+    #[test]
+    fn test_visual_selection() {
+        let reader = TempFile::new("alpha\nbeta\ngamma").reader();
+        
+        // The cursor may sit before the anchor, and both ends are included
+        let chars = Selection::visual(VisualMode::Char, (2, 1), (0, 3), &reader);
+        assert_eq!(chars.get_text(&reader).as_deref(), Some("ha\nbeta\nga"));
+        assert_eq!(chars.byte_len(&reader), 10);
+        let mut written = Vec::new();
+        chars.write_text(&reader, &mut written).unwrap();
+        assert_eq!(written, b"ha\nbeta\nga");
+        
+        let lines = Selection::visual(VisualMode::Line, (1, 2), (2, 0), &reader);
+        assert_eq!(lines.get_text(&reader).as_deref(), Some("beta\ngamma"));
+        assert_eq!(lines.byte_len(&reader), 10);
+        assert_eq!(lines.get_numbered_text(&reader).as_deref(), Some("2  beta\n3  gamma"));
+    }
+}
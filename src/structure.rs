@@ -0,0 +1,405 @@
+use crate::constants::Constants;
+use crate::file_reader::FileReader;
+use crate::syntax::SyntaxKind;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Marks an entry of `element_positions` whose element has siblings of the same name
+const REPEATED_NAME: u32 = 1 << 31;
+/// The entry of `element_positions` for a line that doesn't start an element
+const NO_ELEMENT: u32 = u32::MAX;
+
+/// What kind of construct a block is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    Object,
+    Array,
+    Element,
+}
+
+/// A bracketed region spanning several lines, from the line that opens it to the line
+/// that closes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    /// Nesting depth, where the outermost blocks have depth 0
+    pub depth: usize,
+    pub parent: Option<usize>,
+    /// Position among the parent's children, counting children written on a single line
+    pub index_in_parent: usize,
+    pub kind: BlockKind,
+}
+
+/// A value in the document: either a block or something written on a single line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node {
+    Block(usize),
+    Line(usize),
+}
+
+/// How a line affects nesting
+#[derive(Debug, Default, PartialEq, Eq)]
+struct LineEffect {
+    closes: bool,
+    opens: Option<BlockKind>,
+}
+
+/// The nesting structure of a pretty-printed document, built by a single scan over its lines.
+///
+/// Blocks are found from the line layout alone: a JSON line ending in `{` or `[` opens a
+/// block and one starting with `}` or `]` closes it, and an XML start tag on a line of its
+/// own opens an element that the matching end tag closes. Anything written on one line
+/// cannot be folded, so it is never indexed.
+#[derive(Debug, Default)]
+pub struct StructureIndex {
+    /// Ordered by start line
+    blocks: Vec<Block>,
+    /// Block indices ordered by end line
+    by_end: Vec<usize>,
+    /// For XML, one entry per line: where the element the line starts comes among its
+    /// siblings of the same name, flagged with `REPEATED_NAME` if it has any
+    element_positions: Vec<u32>,
+}
+
+impl StructureIndex {
+    /// Scan every line of a file. Returns `None` if cancelled part way.
+    pub fn build(reader: &FileReader, syntax: SyntaxKind, cancel: &AtomicBool) -> Option<Self> {
+        let mut index = Self::default();
+        let mut open: Vec<usize> = Vec::new();
+        // How many children each open block has had so far, plus one entry for the top level
+        let mut child_counts: Vec<usize> = vec![0];
+        // Likewise, for each element name the number of children with it and the last one's line
+        let mut name_counts: Vec<HashMap<String, (u32, usize)>> = vec![HashMap::new()];
+        
+        for line_num in 0..reader.line_count() {
+            // Checking the flag on every line would dominate the scan
+            if line_num % Constants::STRUCTURE_CANCEL_CHECK_LINES == 0 && cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            
+            let line = reader.get_line(line_num).unwrap_or("");
+            let effect = Self::classify_line(syntax, line);
+            
+            if effect.closes {
+                if let Some(block) = open.pop() {
+                    index.blocks[block].end = line_num;
+                    index.by_end.push(block);
+                    child_counts.pop();
+                    name_counts.pop();
+                }
+            }
+            
+            // Every line other than a closing one starts a child of the innermost open block
+            let starts_child = (!effect.closes || effect.opens.is_some()) && !line.trim().is_empty();
+            let index_in_parent = match child_counts.last_mut() {
+                Some(count) if starts_child => {
+                    *count += 1;
+                    *count - 1
+                }
+                _ => 0,
+            };
+            
+            if syntax == SyntaxKind::Xml {
+                let name = Self::element_name(line).filter(|_| starts_child);
+                let position = match (name, name_counts.last_mut()) {
+                    (Some(name), Some(counts)) => match counts.get_mut(name) {
+                        Some((count, last_line)) => {
+                            index.element_positions[*last_line] |= REPEATED_NAME;
+                            *count += 1;
+                            *last_line = line_num;
+                            (*count - 1) | REPEATED_NAME
+                        }
+                        None => {
+                            counts.insert(name.to_string(), (1, line_num));
+                            0
+                        }
+                    },
+                    _ => NO_ELEMENT,
+                };
+                index.element_positions.push(position);
+            }
+            
+            if let Some(kind) = effect.opens {
+                open.push(index.blocks.len());
+                child_counts.push(0);
+                name_counts.push(HashMap::new());
+                index.blocks.push(Block {
+                    start: line_num,
+                    end: line_num,
+                    depth: open.len() - 1,
+                    parent: open.len().checked_sub(2).map(|parent| open[parent]),
+                    index_in_parent,
+                    kind,
+                });
+            }
+        }
+        
+        // Blocks left open by a truncated document run to the end of the file
+        let last_line = reader.line_count().saturating_sub(1);
+        while let Some(block) = open.pop() {
+            index.blocks[block].end = last_line;
+            index.by_end.push(block);
+        }
+        
+        Some(index)
+    }
+    
+    fn classify_line(syntax: SyntaxKind, line: &str) -> LineEffect {
+        let trimmed = line.trim();
+        match syntax {
+            SyntaxKind::Json => {
+                let closes = trimmed.starts_with(['}', ']']);
+                let opens = match trimmed.trim_end_matches(',').chars().last() {
+                    Some('{') => Some(BlockKind::Object),
+                    Some('[') => Some(BlockKind::Array),
+                    _ => None,
+                };
+                LineEffect { closes, opens }
+            }
+            SyntaxKind::Xml => {
+                // Text is formatted as it was written, so an element's start and end tags can
+                // share lines with its text. Lines are judged by the tags they leave open.
+                let balance = Self::xml_tag_balance(trimmed);
+                LineEffect { closes: balance < 0, opens: (balance > 0).then_some(BlockKind::Element) }
+            }
+        }
+    }
+    
+    /// The name of the element a line starts, if it starts one
+    pub fn element_name(line: &str) -> Option<&str> {
+        let tag = line.trim_start().strip_prefix('<')?;
+        let end = tag.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(tag.len());
+        Some(&tag[..end]).filter(|name| Self::is_element_name(name))
+    }
+    
+    pub fn is_element_name(name: &str) -> bool {
+        !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b':' | b'-' | b'.') || byte >= 0x80)
+    }
+    
+    /// Where the XML element starting on a line comes among its siblings of the same name,
+    /// counting from 0, and whether there are any such siblings
+    pub fn element_position(&self, line: usize) -> Option<(usize, bool)> {
+        let position = *self.element_positions.get(line).filter(|&&position| position != NO_ELEMENT)?;
+        Some(((position & !REPEATED_NAME) as usize, position & REPEATED_NAME != 0))
+    }
+    
+    /// Start tags minus end tags on a line. Start tags that don't finish on the line are not
+    /// counted, and neither is anything inside comments, CDATA or processing instructions.
+    fn xml_tag_balance(line: &str) -> isize {
+        let mut balance = 0;
+        let mut rest = line;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start + 1..];
+            let terminator = if rest.starts_with("!--") {
+                "-->"
+            } else if rest.starts_with("![CDATA[") {
+                "]]>"
+            } else {
+                ">"
+            };
+            let tag_end = rest.find(terminator);
+            
+            if rest.starts_with('/') {
+                balance -= 1;
+            } else if rest.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == ':')
+                && tag_end.is_some_and(|end| !rest[..end].ends_with('/'))
+            {
+                balance += 1;
+            }
+            match tag_end {
+                Some(end) => rest = &rest[end + terminator.len()..],
+                None => break,
+            }
+        }
+        balance
+    }
+    
+    pub fn block(&self, index: usize) -> &Block {
+        &self.blocks[index]
+    }
+    
+    /// The innermost block containing a line, including its opening and closing lines
+    pub fn innermost_at(&self, line: usize) -> Option<usize> {
+        // The last block to start before the line is either the one we want or nested in it
+        let mut candidate = self.blocks.partition_point(|block| block.start <= line).checked_sub(1)?;
+        loop {
+            if self.blocks[candidate].end >= line {
+                return Some(candidate);
+            }
+            candidate = self.blocks[candidate].parent?;
+        }
+    }
+    
+    /// The line at the other end of a block that opens or closes on `line`
+    pub fn matching_line(&self, line: usize) -> Option<usize> {
+        let starting = self.blocks.partition_point(|block| block.start < line);
+        if self.blocks.get(starting).is_some_and(|block| block.start == line) {
+            return Some(self.blocks[starting].end);
+        }
+        
+        let ending = self.by_end.partition_point(|&block| self.blocks[block].end < line);
+        self.by_end
+            .get(ending)
+            .map(|&block| &self.blocks[block])
+            .filter(|block| block.end == line)
+            .map(|block| block.start)
+    }
+    
+    /// The outermost value of the document
+    pub fn root(&self) -> Option<Node> {
+        self.blocks.first().filter(|block| block.depth == 0).map(|_| Node::Block(0))
+    }
+    
+    /// The node a line belongs to, as a child of its innermost enclosing block. Opening and
+    /// closing lines belong to their block.
+    pub fn node_at(&self, line: usize) -> Option<(Node, Option<usize>)> {
+        let block = self.innermost_at(line)?;
+        let Block { start, end, parent, .. } = self.blocks[block];
+        if line == start || line == end {
+            Some((Node::Block(block), parent))
+        } else {
+            Some((Node::Line(line), Some(block)))
+        }
+    }
+    
+    /// Position of a node among its parent's children
+    pub fn index_in_parent(&self, node: Node, parent: usize) -> usize {
+        match node {
+            Node::Block(block) => self.blocks[block].index_in_parent,
+            Node::Line(line) => {
+                // Count on from the last sibling block before the line, one child per line
+                let mut candidate = self.blocks.partition_point(|block| block.start < line).checked_sub(1);
+                while let Some(block) = candidate.filter(|&block| block > parent) {
+                    if self.blocks[block].parent == Some(parent) {
+                        let sibling = &self.blocks[block];
+                        return sibling.index_in_parent + (line - sibling.end);
+                    }
+                    candidate = self.blocks[block].parent;
+                }
+                line - self.blocks[parent].start - 1
+            }
+        }
+    }
+    
+    /// The direct children of a block, in document order
+    pub fn children(&self, block: usize) -> impl Iterator<Item = Node> + '_ {
+        let Block { start, end, .. } = self.blocks[block];
+        self.nodes_between(start + 1, end, block + 1)
+    }
+    
+    /// The nodes outside every block, given the number of lines in the document
+    pub fn top_level(&self, line_count: usize) -> impl Iterator<Item = Node> + '_ {
+        self.nodes_between(0, line_count, 0)
+    }
+    
+    /// Consecutive sibling nodes from `line` up to `end`, where `next_block` is the first block
+    /// starting at or after `line`
+    fn nodes_between(&self, mut line: usize, end: usize, mut next_block: usize) -> impl Iterator<Item = Node> + '_ {
+        std::iter::from_fn(move || {
+            if line >= end {
+                return None;
+            }
+            
+            let node = match self.blocks.get(next_block).filter(|child| child.start == line) {
+                Some(child) => {
+                    let node = Node::Block(next_block);
+                    line = child.end + 1;
+                    next_block = self.blocks.partition_point(|block| block.start < line);
+                    node
+                }
+                None => {
+                    let node = Node::Line(line);
+                    line += 1;
+                    node
+                }
+            };
+            Some(node)
+        })
+    }
+    
+    /// A block and every block nested inside it, which are stored contiguously
+    pub fn descendant_blocks(&self, block: usize) -> std::ops::Range<usize> {
+        let end = self.blocks[block].end;
+        block..self.blocks.partition_point(|other| other.start <= end)
+    }
+    
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+    
+    pub fn start_line(&self, node: Node) -> usize {
+        match node {
+            Node::Block(block) => self.blocks[block].start,
+            Node::Line(line) => line,
+        }
+    }
+    
+    /// Indices of all blocks at a nesting depth
+    pub fn blocks_at_depth(&self, depth: usize) -> impl Iterator<Item = usize> + '_ {
+        self.blocks.iter().enumerate().filter(move |(_, block)| block.depth == depth).map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::TempFile;
+    
+    fn index_of(text: &str, syntax: SyntaxKind) -> StructureIndex {
+        let reader = TempFile::new(text).reader();
+        StructureIndex::build(&reader, syntax, &AtomicBool::new(false)).unwrap()
+    }
+    
+    #[test]
+    fn test_json_structure() {
+        let text = "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": {\n    \"c\": 2\n  }\n}";
+        let index = index_of(text, SyntaxKind::Json);
+        
+        let spans: Vec<(usize, usize, usize)> = index.blocks.iter().map(|block| (block.start, block.end, block.depth)).collect();
+        assert_eq!(spans, vec![(0, 8, 0), (1, 4, 1), (5, 7, 1)]);
+        assert_eq!(index.block(1).kind, BlockKind::Array);
+        assert_eq!(index.block(2).parent, Some(0));
+        
+        assert_eq!(index.innermost_at(3), Some(1));
+        assert_eq!(index.innermost_at(5), Some(2));
+        assert_eq!(index.innermost_at(8), Some(0));
+        assert_eq!(index.matching_line(1), Some(4));
+        assert_eq!(index.matching_line(7), Some(5));
+        assert_eq!(index.matching_line(2), None);
+        assert_eq!(index.blocks_at_depth(1).collect::<Vec<_>>(), vec![1, 2]);
+        
+        assert_eq!(index.children(1).collect::<Vec<_>>(), vec![Node::Line(2), Node::Line(3)]);
+        assert_eq!(index.children(0).collect::<Vec<_>>(), vec![Node::Block(1), Node::Block(2)]);
+        assert_eq!(index.block(2).index_in_parent, 1);
+        assert_eq!(index.node_at(3), Some((Node::Line(3), Some(1))));
+        assert_eq!(index.index_in_parent(Node::Line(3), 1), 1);
+        assert_eq!(index.descendant_blocks(0), 0..3);
+    }
+    
+    #[test]
+    fn test_xml_structure() {
+        let text = "<?xml version=\"1.0\"?>\n<root>\n    <a x=\"1\">text</a>\n    <b>\n        <c/>\n    </b>\n</root>";
+        let index = index_of(text, SyntaxKind::Xml);
+        
+        let spans: Vec<(usize, usize, usize)> = index.blocks.iter().map(|block| (block.start, block.end, block.depth)).collect();
+        assert_eq!(spans, vec![(1, 6, 0), (3, 5, 1)]);
+        assert_eq!(index.matching_line(6), Some(1));
+        assert_eq!(index.top_level(7).collect::<Vec<_>>(), vec![Node::Line(0), Node::Block(0)]);
+        assert_eq!(index.children(0).collect::<Vec<_>>(), vec![Node::Line(2), Node::Block(1)]);
+        assert_eq!(index.element_position(2), Some((0, false)));
+        assert_eq!(index.element_position(6), None);
+        
+        // Elements are counted among the siblings with their name only
+        let text = "<root>\n    <a/>\n    <b>\n    </b>\n    <a>x</a>\n    <a/>\n</root>";
+        let index = index_of(text, SyntaxKind::Xml);
+        let positions: Vec<_> = (0..7).map(|line| index.element_position(line)).collect();
+        assert_eq!(positions, vec![Some((0, false)), Some((0, true)), Some((0, false)), None, Some((1, true)), Some((2, true)), None]);
+        
+        // Text keeps its own line breaks, and markup inside comments doesn't count
+        let text = "<root>\n    <p>Some <b>bold</b>\ntext</p>\n    <!-- <p> -->\n    <q><![CDATA[<x>]]>\n</q>\n</root>";
+        let index = index_of(text, SyntaxKind::Xml);
+        let spans: Vec<(usize, usize)> = index.blocks.iter().map(|block| (block.start, block.end)).collect();
+        assert_eq!(spans, vec![(0, 6), (1, 2), (4, 5)]);
+    }
+}
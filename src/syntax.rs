@@ -0,0 +1,313 @@
+use crate::constants::Constants;
+use crate::formatter::FormatKind;
+use ratatui::style::Style;
+
+/// Languages the viewer can colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Json,
+    Xml,
+}
+
+impl SyntaxKind {
+    /// The colouring for a format, if it has one
+    pub fn for_format(kind: FormatKind) -> Option<Self> {
+        match kind {
+            FormatKind::Json | FormatKind::JsonLines => Some(SyntaxKind::Json),
+            FormatKind::Xml => Some(SyntaxKind::Xml),
+            FormatKind::Yaml
+            | FormatKind::Toml
+            | FormatKind::Csv
+            | FormatKind::Tsv
+            | FormatKind::Html
+            | FormatKind::JavaScript
+            | FormatKind::Css => None,
+        }
+    }
+}
+
+/// Tokenizer state carried from the end of one line to the start of the next.
+///
+/// JSON tokens never span lines, so JSON lines always start `Normal`. XML comments, CDATA
+/// sections, processing instructions and tags can continue onto following lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineState {
+    #[default]
+    Normal,
+    Comment,
+    CData,
+    Instruction,
+    Tag,
+}
+
+/// Walks a line byte by byte while keeping track of the character position
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    char_pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(line: &'a str) -> Self {
+        // Only the start of very long lines can be on screen, so the rest is left uncoloured
+        let mut end = line.len().min(Constants::SYNTAX_HIGHLIGHT_MAX_BYTES);
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        Self { bytes: &line.as_bytes()[..end], pos: 0, char_pos: 0 }
+    }
+    
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+    
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+    
+    fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.bytes[self.pos..].starts_with(prefix)
+    }
+    
+    fn advance(&mut self) {
+        if let Some(byte) = self.peek() {
+            self.pos += 1;
+            // UTF-8 continuation bytes don't start a new character
+            if byte & 0xC0 != 0x80 {
+                self.char_pos += 1;
+            }
+        }
+    }
+    
+    fn advance_by(&mut self, count: usize) {
+        for _ in 0..count {
+            self.advance();
+        }
+    }
+    
+    fn advance_while(&mut self, predicate: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.advance();
+        }
+    }
+    
+    /// Advance past `terminator`, or to the end of the line. Returns whether it was found.
+    fn advance_past(&mut self, terminator: &[u8]) -> bool {
+        while !self.at_end() {
+            if self.starts_with(terminator) {
+                self.advance_by(terminator.len());
+                return true;
+            }
+            self.advance();
+        }
+        false
+    }
+}
+
+pub struct SyntaxHighlighter;
+
+impl SyntaxHighlighter {
+    /// Append styled character ranges for the tokens of one line to `ranges`, starting from
+    /// `state`. Returns the state the next line starts in.
+    pub fn highlight(kind: SyntaxKind, line: &str, state: LineState, ranges: &mut Vec<(usize, usize, Style)>) -> LineState {
+        let mut scanner = Scanner::new(line);
+        match kind {
+            SyntaxKind::Json => {
+                Self::highlight_json(&mut scanner, ranges);
+                LineState::Normal
+            }
+            SyntaxKind::Xml => Self::highlight_xml(&mut scanner, state, ranges),
+        }
+    }
+    
+    fn highlight_json(scanner: &mut Scanner, ranges: &mut Vec<(usize, usize, Style)>) {
+        while let Some(byte) = scanner.peek() {
+            let start = scanner.char_pos;
+            let color = match byte {
+                b'"' => {
+                    Self::skip_json_string(scanner);
+                    let end = scanner.char_pos;
+                    
+                    // A string followed by a colon is an object key
+                    let checkpoint = (scanner.pos, scanner.char_pos);
+                    scanner.advance_while(|byte| byte == b' ' || byte == b'\t');
+                    let is_key = scanner.peek() == Some(b':');
+                    (scanner.pos, scanner.char_pos) = checkpoint;
+                    
+                    let color = if is_key { Constants::SYNTAX_KEY_COLOR } else { Constants::SYNTAX_STRING_COLOR };
+                    ranges.push((start, end, Style::default().fg(color)));
+                    continue;
+                }
+                b'-' | b'0'..=b'9' => {
+                    scanner.advance_while(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'));
+                    Some(Constants::SYNTAX_NUMBER_COLOR)
+                }
+                b'a'..=b'z' => {
+                    let word_start = scanner.pos;
+                    scanner.advance_while(|byte| byte.is_ascii_alphabetic());
+                    match &scanner.bytes[word_start..scanner.pos] {
+                        b"true" | b"false" => Some(Constants::SYNTAX_BOOLEAN_COLOR),
+                        b"null" => Some(Constants::SYNTAX_NULL_COLOR),
+                        _ => None,
+                    }
+                }
+                _ => {
+                    scanner.advance();
+                    None
+                }
+            };
+            
+            if let Some(color) = color {
+                ranges.push((start, scanner.char_pos, Style::default().fg(color)));
+            }
+        }
+    }
+    
+    fn skip_json_string(scanner: &mut Scanner) {
+        scanner.advance();
+        while let Some(byte) = scanner.peek() {
+            scanner.advance();
+            match byte {
+                b'\\' => scanner.advance(),
+                b'"' => return,
+                _ => {}
+            }
+        }
+    }
+    
+    fn highlight_xml(scanner: &mut Scanner, mut state: LineState, ranges: &mut Vec<(usize, usize, Style)>) -> LineState {
+        let comment_style = Style::default().fg(Constants::SYNTAX_COMMENT_COLOR);
+        let tag_style = Style::default().fg(Constants::SYNTAX_TAG_COLOR);
+        
+        while !scanner.at_end() {
+            let start = scanner.char_pos;
+            state = match state {
+                LineState::Comment => {
+                    let closed = scanner.advance_past(b"-->");
+                    ranges.push((start, scanner.char_pos, comment_style));
+                    if closed { LineState::Normal } else { LineState::Comment }
+                }
+                LineState::CData => {
+                    let closed = scanner.advance_past(b"]]>");
+                    ranges.push((start, scanner.char_pos, Style::default().fg(Constants::SYNTAX_STRING_COLOR)));
+                    if closed { LineState::Normal } else { LineState::CData }
+                }
+                LineState::Instruction => {
+                    let closed = scanner.advance_past(b"?>");
+                    ranges.push((start, scanner.char_pos, comment_style));
+                    if closed { LineState::Normal } else { LineState::Instruction }
+                }
+                LineState::Tag => Self::highlight_xml_tag_body(scanner, ranges),
+                LineState::Normal => {
+                    if scanner.starts_with(b"<!--") {
+                        LineState::Comment
+                    } else if scanner.starts_with(b"<![CDATA[") {
+                        LineState::CData
+                    } else if scanner.starts_with(b"<?") {
+                        LineState::Instruction
+                    } else if scanner.starts_with(b"<") {
+                        // The opening bracket and name of an element, declaration or end tag
+                        scanner.advance();
+                        scanner.advance_while(|byte| matches!(byte, b'/' | b'!'));
+                        scanner.advance_while(Self::is_xml_name_byte);
+                        ranges.push((start, scanner.char_pos, tag_style));
+                        LineState::Tag
+                    } else {
+                        // Text content up to the next markup
+                        scanner.advance_while(|byte| byte != b'<');
+                        ranges.push((start, scanner.char_pos, Style::default().fg(Constants::SYNTAX_TEXT_COLOR)));
+                        LineState::Normal
+                    }
+                }
+            };
+        }
+        
+        state
+    }
+    
+    /// Attributes inside a tag, up to and including the closing `>` or `/>`
+    fn highlight_xml_tag_body(scanner: &mut Scanner, ranges: &mut Vec<(usize, usize, Style)>) -> LineState {
+        while let Some(byte) = scanner.peek() {
+            let start = scanner.char_pos;
+            match byte {
+                b'>' | b'/' | b'?' => {
+                    scanner.advance();
+                    if byte == b'>' || scanner.peek() == Some(b'>') {
+                        if byte != b'>' {
+                            scanner.advance();
+                        }
+                        ranges.push((start, scanner.char_pos, Style::default().fg(Constants::SYNTAX_TAG_COLOR)));
+                        return LineState::Normal;
+                    }
+                }
+                b'"' | b'\'' => {
+                    scanner.advance();
+                    scanner.advance_while(|next| next != byte);
+                    scanner.advance();
+                    ranges.push((start, scanner.char_pos, Style::default().fg(Constants::SYNTAX_STRING_COLOR)));
+                }
+                _ if Self::is_xml_name_byte(byte) => {
+                    scanner.advance_while(Self::is_xml_name_byte);
+                    ranges.push((start, scanner.char_pos, Style::default().fg(Constants::SYNTAX_ATTRIBUTE_COLOR)));
+                }
+                _ => scanner.advance(),
+            }
+        }
+        LineState::Tag
+    }
+    
+    fn is_xml_name_byte(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b':' | b'-' | b'.') || byte >= 0x80
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+    
+    fn colored(kind: SyntaxKind, line: &str, state: LineState) -> (Vec<(String, Color)>, LineState) {
+        let mut ranges = Vec::new();
+        let state = SyntaxHighlighter::highlight(kind, line, state, &mut ranges);
+        let chars: Vec<char> = line.chars().collect();
+        let tokens = ranges
+            .into_iter()
+            .map(|(start, end, style)| (chars[start..end].iter().collect(), style.fg.unwrap()))
+            .collect();
+        (tokens, state)
+    }
+    
+    #[test]
+    fn test_highlight_json() {
+        let (tokens, _) = colored(SyntaxKind::Json, r#"  "kéy": ["s\"", -1.5e3, true, null],"#, LineState::Normal);
+        assert_eq!(tokens, vec![
+            ("\"kéy\"".to_string(), Constants::SYNTAX_KEY_COLOR),
+            ("\"s\\\"\"".to_string(), Constants::SYNTAX_STRING_COLOR),
+            ("-1.5e3".to_string(), Constants::SYNTAX_NUMBER_COLOR),
+            ("true".to_string(), Constants::SYNTAX_BOOLEAN_COLOR),
+            ("null".to_string(), Constants::SYNTAX_NULL_COLOR),
+        ]);
+    }
+    
+    #[test]
+    fn test_highlight_xml() {
+        let (tokens, state) = colored(SyntaxKind::Xml, r#"<a href="x">text</a><!-- open"#, LineState::Normal);
+        assert_eq!(tokens, vec![
+            ("<a".to_string(), Constants::SYNTAX_TAG_COLOR),
+            ("href".to_string(), Constants::SYNTAX_ATTRIBUTE_COLOR),
+            ("\"x\"".to_string(), Constants::SYNTAX_STRING_COLOR),
+            (">".to_string(), Constants::SYNTAX_TAG_COLOR),
+            ("text".to_string(), Constants::SYNTAX_TEXT_COLOR),
+            ("</a".to_string(), Constants::SYNTAX_TAG_COLOR),
+            (">".to_string(), Constants::SYNTAX_TAG_COLOR),
+            ("<!-- open".to_string(), Constants::SYNTAX_COMMENT_COLOR),
+        ]);
+        assert_eq!(state, LineState::Comment);
+        
+        // The comment carries over to the next line
+        let (tokens, state) = colored(SyntaxKind::Xml, "close --><b/>", state);
+        assert_eq!(tokens[0], ("close -->".to_string(), Constants::SYNTAX_COMMENT_COLOR));
+        assert_eq!(tokens[2], ("/>".to_string(), Constants::SYNTAX_TAG_COLOR));
+        assert_eq!(state, LineState::Normal);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::constants::Constants;
+use crate::file_reader::FileReader;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+/// State of the table view of a delimited file: which columns are hidden and which one the
+/// view is scrolled to.
+///
+/// The first line is the header. Each line is parsed on its own when it is drawn, so quoted
+/// fields can contain the delimiter but not line breaks.
+#[derive(Debug, Clone)]
+pub struct TableView {
+    delimiter: char,
+    /// The leftmost column shown, hidden or not
+    first_column: usize,
+    hidden: BTreeSet<usize>,
+}
+
+impl TableView {
+    pub fn new(delimiter: char) -> Self {
+        Self { delimiter, first_column: 0, hidden: BTreeSet::new() }
+    }
+    
+    /// Split a line into fields, unquoting `"..."` fields and their doubled `""` quotes
+    pub fn parse_row(&self, line: &str) -> Vec<String> {
+        self.parse_row_spans(line).into_iter().map(|(field, _)| field).collect()
+    }
+    
+    /// Like `parse_row`, but also returns the character range each field takes up in the line,
+    /// quotes included, so that highlighting in the line can be matched to its cells
+    pub fn parse_row_spans(&self, line: &str) -> Vec<(String, Range<usize>)> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut field_start = 0;
+        let mut in_quotes = false;
+        let mut quoted = false;
+        let mut chars = line.chars().enumerate().peekable();
+        
+        while let Some((index, c)) = chars.next() {
+            if in_quotes {
+                match c {
+                    '"' if chars.peek().map(|&(_, next)| next) == Some('"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    '"' => in_quotes = false,
+                    _ => field.push(c),
+                }
+            } else if c == self.delimiter {
+                fields.push((std::mem::take(&mut field), field_start..index));
+                field_start = index + 1;
+                quoted = false;
+            } else if c == '"' && field.is_empty() && !quoted {
+                in_quotes = true;
+                quoted = true;
+            } else {
+                field.push(c);
+            }
+        }
+        fields.push((field, field_start..line.chars().count()));
+        fields
+    }
+    
+    /// Column names from the header line
+    pub fn header(&self, reader: &FileReader) -> Vec<String> {
+        reader.get_line(0).map(|line| self.parse_row(line)).unwrap_or_default()
+    }
+    
+    /// Column widths fitted to the header and to the rows in `sample`, capped so that one long
+    /// value can't push every other column off screen
+    pub fn column_widths(&self, reader: &FileReader, sample: Range<usize>) -> Vec<usize> {
+        let mut widths: Vec<usize> = Vec::new();
+        let rows = std::iter::once(0).chain(sample.start.max(1)..sample.end.min(reader.line_count()));
+        for line_num in rows {
+            let Some(line) = reader.get_line(line_num) else {
+                continue;
+            };
+            
+            for (column, field) in self.parse_row(line).iter().enumerate() {
+                let width = field.chars().count().min(Constants::TABLE_MAX_COLUMN_WIDTH);
+                match widths.get_mut(column) {
+                    Some(existing) => *existing = (*existing).max(width),
+                    None => widths.push(width),
+                }
+            }
+        }
+        widths
+    }
+    
+    /// The columns to draw from the left edge of the view, given how many there are
+    pub fn visible_columns(&self, column_count: usize) -> impl Iterator<Item = usize> + '_ {
+        (self.first_column..column_count).filter(|column| !self.hidden.contains(column))
+    }
+    
+    /// Move the left edge of the view by `delta` shown columns
+    pub fn scroll_columns(&mut self, delta: isize, column_count: usize) {
+        let shown: Vec<usize> = (0..column_count).filter(|column| !self.hidden.contains(column)).collect();
+        let Some(position) = shown.iter().position(|&column| column >= self.first_column).or(shown.len().checked_sub(1)) else {
+            return;
+        };
+        let target = position.saturating_add_signed(delta).min(shown.len() - 1);
+        self.first_column = shown[target];
+    }
+    
+    /// Bring a column to the left edge of the view, showing it again if it was hidden
+    pub fn scroll_to_column(&mut self, column: usize) {
+        self.hidden.remove(&column);
+        self.first_column = column;
+    }
+    
+    /// Hide a column, unless it is the last one shown
+    pub fn hide_column(&mut self, column: usize, column_count: usize) -> bool {
+        if column >= column_count || self.hidden.len() + 1 >= column_count {
+            return false;
+        }
+        self.hidden.insert(column);
+        if column == self.first_column {
+            self.scroll_columns(0, column_count);
+        }
+        true
+    }
+    
+    pub fn show_all_columns(&mut self) {
+        self.hidden.clear();
+    }
+    
+    pub fn first_column(&self) -> usize {
+        self.first_column
+    }
+    
+    pub fn hidden_count(&self) -> usize {
+        self.hidden.len()
+    }
+    
+    /// Find a column by 1-based number, or by name: an exact match first, then a name starting
+    /// with the text, ignoring case either way
+    pub fn find_column(header: &[String], query: &str) -> Option<usize> {
+        let query = query.trim();
+        if let Ok(number) = query.parse::<usize>() {
+            return (1..=header.len()).contains(&number).then(|| number - 1);
+        }
+        
+        let query = query.to_lowercase();
+        let names: Vec<String> = header.iter().map(|name| name.trim().to_lowercase()).collect();
+        names.iter().position(|name| *name == query).or_else(|| names.iter().position(|name| name.starts_with(&query)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_row() {
+        let csv = TableView::new(',');
+        assert_eq!(csv.parse_row("a,\"b, c\",\"say \"\"hi\"\"\",\r"), vec!["a", "b, c", "say \"hi\"", ""]);
+        assert_eq!(csv.parse_row("x\"y,\"\"z"), vec!["x\"y", "z"]);
+        assert_eq!(TableView::new('\t').parse_row("a\tb,c"), vec!["a", "b,c"]);
+        
+        let spans: Vec<Range<usize>> = csv.parse_row_spans("a,\"b,c\",d").into_iter().map(|(_, span)| span).collect();
+        assert_eq!(spans, vec![0..1, 2..7, 8..9]);
+    }
+    
+    #[test]
+    fn test_columns() {
+        let mut table = TableView::new(',');
+        assert!(table.hide_column(1, 4));
+        assert_eq!(table.visible_columns(4).collect::<Vec<_>>(), vec![0, 2, 3]);
+        
+        // Scrolling steps over hidden columns and stops at the last one
+        table.scroll_columns(1, 4);
+        assert_eq!(table.first_column(), 2);
+        table.scroll_columns(5, 4);
+        assert_eq!(table.first_column(), 3);
+        table.scroll_columns(-5, 4);
+        assert_eq!(table.first_column(), 0);
+        
+        table.scroll_to_column(1);
+        assert_eq!(table.hidden_count(), 0);
+        assert_eq!(table.visible_columns(4).collect::<Vec<_>>(), vec![1, 2, 3]);
+        
+        // The last column shown can't be hidden
+        assert!(table.hide_column(0, 2));
+        assert!(!table.hide_column(1, 2));
+    }
+    
+    #[test]
+    fn test_find_column() {
+        let header: Vec<String> = ["id", "Name", "name_full"].iter().map(|name| name.to_string()).collect();
+        assert_eq!(TableView::find_column(&header, "2"), Some(1));
+        assert_eq!(TableView::find_column(&header, "NAME"), Some(1));
+        assert_eq!(TableView::find_column(&header, "name_"), Some(2));
+        assert_eq!(TableView::find_column(&header, "4"), None);
+        assert_eq!(TableView::find_column(&header, "missing"), None);
+    }
+}
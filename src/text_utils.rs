@@ -1,5 +1,5 @@
 /// Utilities for safe text manipulation with proper UTF-8 handling
-use ratatui::{style::Style, text::Span};
+use ratatui::style::Style;
 
 pub struct TextUtils;
 
@@ -19,88 +19,77 @@ impl TextUtils {
         chars[safe_start..safe_end].iter().collect()
     }
     
-    /// Split a line into spans based on character ranges, handling overlapping ranges
-    pub fn split_line_into_spans<'a>(line: &'a str, ranges: &[(usize, usize, Style)]) -> Vec<Span<'a>> {
-        let chars: Vec<char> = line.chars().collect();
-        let char_len = chars.len();
-        let mut spans = Vec::new();
-        
+    /// Resolve styled character ranges into contiguous byte segments covering the whole line.
+    ///
+    /// Ranges may overlap; later ranges are patched on top of earlier ones, so callers list
+    /// them from lowest to highest priority. The returned byte offsets always fall on char
+    /// boundaries and can be used to slice `line` directly.
+    pub fn layout_segments(line: &str, ranges: &[(usize, usize, Style)]) -> Vec<(usize, usize, Style)> {
         if ranges.is_empty() {
-            return vec![Span::raw(line)];
+            return vec![(0, line.len(), Style::default())];
         }
         
-        // Sort ranges by start position
-        let mut sorted_ranges = ranges.to_vec();
-        sorted_ranges.sort_by(|a, b| a.0.cmp(&b.0));
-        
-        // Create a priority map where later styles have higher priority
-        let mut style_map: Vec<(usize, usize, Style)> = Vec::new();
-        
-        // Process ranges to handle overlaps by giving priority to later ranges
-        for &(start, end, style) in &sorted_ranges {
-            let safe_start = start.min(char_len);
-            let safe_end = end.min(char_len);
-            
-            if safe_start < safe_end {
-                style_map.push((safe_start, safe_end, style));
+        let mut boundaries: Vec<usize> = Vec::with_capacity(ranges.len() * 2 + 1);
+        boundaries.push(0);
+        for &(start, end, _) in ranges {
+            if start < end {
+                boundaries.push(start);
+                boundaries.push(end);
             }
         }
+        boundaries.sort_unstable();
+        boundaries.dedup();
         
-        // Create segments with their styles
-        let mut segments: Vec<(usize, usize, Option<Style>)> = Vec::new();
-        let mut events: Vec<(usize, bool, Style)> = Vec::new(); // (position, is_start, style)
-        
-        // Create start/end events
-        for &(start, end, style) in &style_map {
-            events.push((start, true, style));
-            events.push((end, false, style));
-        }
-        
-        // Sort events by position, with end events before start events at same position
-        events.sort_by(|a, b| {
-            match a.0.cmp(&b.0) {
-                std::cmp::Ordering::Equal => a.1.cmp(&b.1), // false (end) before true (start)
-                other => other,
+        // Map the character boundaries to byte offsets in a single pass over the line
+        let mut byte_offsets = Vec::with_capacity(boundaries.len());
+        let mut char_indices = line.char_indices().map(|(byte, _)| byte).chain(std::iter::once(line.len()));
+        let mut char_pos = 0;
+        let mut byte_pos = char_indices.next().unwrap_or(line.len());
+        for &boundary in &boundaries {
+            while char_pos < boundary && byte_pos < line.len() {
+                byte_pos = char_indices.next().unwrap_or(line.len());
+                char_pos += 1;
             }
-        });
-        
-        let mut active_styles: Vec<Style> = Vec::new();
-        let mut last_pos = 0;
+            byte_offsets.push(byte_pos);
+        }
         
-        for (pos, is_start, style) in events {
-            // Add segment before this event
-            if pos > last_pos {
-                let current_style = active_styles.last().copied();
-                segments.push((last_pos, pos, current_style));
+        let mut segments: Vec<(usize, usize, Style)> = Vec::new();
+        for (i, &start_char) in boundaries.iter().enumerate() {
+            let start_byte = byte_offsets[i];
+            let end_byte = byte_offsets.get(i + 1).copied().unwrap_or(line.len());
+            if start_byte >= end_byte {
+                continue;
             }
             
-            if is_start {
-                active_styles.push(style);
-            } else {
-                active_styles.retain(|&s| s != style);
-            }
+            let style = ranges
+                .iter()
+                .filter(|&&(start, end, _)| start <= start_char && start_char < end)
+                .fold(Style::default(), |style, &(_, _, patch)| style.patch(patch));
             
-            last_pos = pos;
+            match segments.last_mut() {
+                Some(last) if last.2 == style && last.1 == start_byte => last.1 = end_byte,
+                _ => segments.push((start_byte, end_byte, style)),
+            }
         }
         
-        // Add final segment
-        if last_pos < char_len {
-            segments.push((last_pos, char_len, None));
+        if segments.is_empty() {
+            segments.push((0, line.len(), Style::default()));
         }
         
-        // Convert segments to spans
-        for (start, end, style_opt) in segments {
-            if start < end {
-                let text: String = chars[start..end].iter().collect();
-                if let Some(style) = style_opt {
-                    spans.push(Span::styled(text, style));
-                } else {
-                    spans.push(Span::raw(text));
-                }
+        segments
+    }
+    
+    /// Format a count with thousands separators, e.g. `1,204`
+    pub fn format_count(count: usize) -> String {
+        let digits = count.to_string();
+        let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                formatted.push(',');
             }
+            formatted.push(digit);
         }
-        
-        spans
+        formatted
     }
     
     /// Get the character length of a string (not byte length)
@@ -108,6 +97,55 @@ impl TextUtils {
         text.chars().count()
     }
     
+    /// Find every non-overlapping occurrence of `needle`, returned as character ranges
+    pub fn find_char_ranges(text: &str, needle: &str) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        if needle.is_empty() {
+            return ranges;
+        }
+        
+        let needle_chars = Self::char_len(needle);
+        let mut byte_pos = 0;
+        let mut char_pos = 0;
+        
+        for (byte_start, _) in text.match_indices(needle) {
+            char_pos += Self::char_len(&text[byte_pos..byte_start]);
+            ranges.push((char_pos, char_pos + needle_chars));
+            char_pos += needle_chars;
+            byte_pos = byte_start + needle.len();
+        }
+        
+        ranges
+    }
+    
+    /// The character range of the word around character `col`, where a word is a run of
+    /// letters, digits, `_` and `word_chars`. The extra characters only join words together,
+    /// so they are trimmed from both ends: `10.0.0.1.` gives `10.0.0.1`.
+    pub fn word_at(line: &str, col: usize, word_chars: &str) -> Option<(usize, usize)> {
+        let chars: Vec<char> = line.chars().collect();
+        let is_core = |c: char| c.is_alphanumeric() || c == '_';
+        let is_word = |c: char| is_core(c) || word_chars.contains(c);
+        if !chars.get(col).is_some_and(|&c| is_word(c)) {
+            return None;
+        }
+        
+        let mut start = col;
+        while start > 0 && is_word(chars[start - 1]) {
+            start -= 1;
+        }
+        let mut end = col + 1;
+        while end < chars.len() && is_word(chars[end]) {
+            end += 1;
+        }
+        
+        while start < end && !is_core(chars[start]) {
+            start += 1;
+        }
+        while end > start && !is_core(chars[end - 1]) {
+            end -= 1;
+        }
+        (start < end).then_some((start, end))
+    }
 }
 
 #[cfg(test)]
@@ -129,4 +167,60 @@ mod tests {
         assert_eq!(TextUtils::char_len("世界"), 2);
         assert_eq!(TextUtils::char_len(""), 0);
     }
+    
+    #[test]
+    fn test_format_count() {
+        assert_eq!(TextUtils::format_count(0), "0");
+        assert_eq!(TextUtils::format_count(999), "999");
+        assert_eq!(TextUtils::format_count(1204), "1,204");
+        assert_eq!(TextUtils::format_count(12_345_678), "12,345,678");
+    }
+    
+    #[test]
+    fn test_find_char_ranges() {
+        assert_eq!(TextUtils::find_char_ranges("abcabc", "bc"), vec![(1, 3), (4, 6)]);
+        assert_eq!(TextUtils::find_char_ranges("日本語 error, 世界 error", "error"), vec![(4, 9), (14, 19)]);
+        assert_eq!(TextUtils::find_char_ranges("ééé", "é"), vec![(0, 1), (1, 2), (2, 3)]);
+        assert!(TextUtils::find_char_ranges("abc", "").is_empty());
+    }
+    
+    #[test]
+    fn test_word_at() {
+        let line = "id=3f2b-9c1e from 10.0.0.1. at 12:30:05 _tmp";
+        assert_eq!(TextUtils::word_at(line, 5, "-.:"), Some((3, 12)));
+        assert_eq!(TextUtils::word_at(line, 20, "-.:"), Some((18, 26)));
+        assert_eq!(TextUtils::word_at(line, 35, "-.:"), Some((31, 39)));
+        assert_eq!(TextUtils::word_at(line, 5, ""), Some((3, 7)));
+        assert_eq!(TextUtils::word_at(line, 41, "-.:"), Some((40, 44)));
+        assert_eq!(TextUtils::word_at(line, 2, "-.:"), None);
+        assert_eq!(TextUtils::word_at(line, 100, "-.:"), None);
+    }
+    
+    #[test]
+    fn test_layout_segments() {
+        use ratatui::style::Color;
+        
+        let red = Style::default().fg(Color::Red);
+        let blue_bg = Style::default().bg(Color::Blue);
+        
+        let line = "日本 error 世界";
+        let segments = TextUtils::layout_segments(line, &[(3, 8, red)]);
+        let texts: Vec<&str> = segments.iter().map(|&(start, end, _)| &line[start..end]).collect();
+        assert_eq!(texts, vec!["日本 ", "error", " 世界"]);
+        assert_eq!(segments[1].2, red);
+        
+        // Later ranges are patched over earlier ones
+        let segments = TextUtils::layout_segments("abcdef", &[(0, 4, red), (2, 6, blue_bg)]);
+        assert_eq!(segments, vec![
+            (0, 2, red),
+            (2, 4, red.patch(blue_bg)),
+            (4, 6, blue_bg),
+        ]);
+        
+        // Out of range offsets are clamped to the end of the line
+        assert_eq!(TextUtils::layout_segments("ab", &[(1, 10, red)]), vec![
+            (0, 1, Style::default()),
+            (1, 2, red),
+        ]);
+    }
 }
\ No newline at end of file
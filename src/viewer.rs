@@ -1,186 +1,693 @@
 use crate::{
-    file_reader::FileReader,
-    selection::Selection,
+    app_event::{self, AppEvent, CommandOutput},
+    bookmarks::{JumpList, Marks},
+    clipboard_backend::ClipboardBackend,
+    context_menu::{ContextMenu, MenuAction},
+    expansion::RecordExpansions,
+    file_reader::{FileChange, FileReader, SearchMatch},
+    formatter::{FileFormatter, FormatKind, FormattedSource, LineMap, SyntaxError},
+    goto::GotoTarget,
+    query::DocumentQuery,
+    selection::{Selection, VisualMode},
+    structure::{Block as StructureBlock, BlockKind, StructureIndex},
+    syntax::{LineState, SyntaxHighlighter, SyntaxKind},
+    table::TableView,
     text_utils::TextUtils,
     constants::Constants,
 };
 use tui_textarea::TextArea;
-use clipboard::ClipboardProvider;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::Style,
-    text::{Line, Span},
-    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
+    style::{Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Gauge, Paragraph},
     Frame,
 };
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{BufWriter, Read as _, Write as _};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone)]
-pub struct ContextMenu {
-    pub x: u16,
-    pub y: u16,
-    pub items: Vec<String>,
+/// Byte segments of a line together with the style each one is drawn with
+type Segments = Vec<(usize, usize, Style)>;
+
+/// A line's segments along with the syntax states the line starts in and the next one starts in
+struct LineLayout {
+    segments: Segments,
+    start_state: LineState,
+    end_state: LineState,
 }
 
-impl ContextMenu {
-    pub fn new(x: u16, y: u16) -> Self {
-        Self {
-            x,
-            y,
-            items: vec![
-                Constants::CONTEXT_MENU_COPY.to_string(),
-                Constants::CONTEXT_MENU_SEARCH.to_string(),
-            ],
+/// The highlight state a cached line layout was computed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LayoutKey {
+    selection: Option<(usize, usize, usize, usize)>,
+    search_generation: u64,
+    current_match: usize,
+}
+
+/// What a screen row of the content area shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DisplayRow {
+    Line(usize),
+    /// A row of an expanded record, pretty-printed in place of its line
+    Expansion { line: usize, row: usize },
+    /// A closed fold, drawn as its first line followed by a summary
+    Fold(StructureBlock),
+}
+
+impl DisplayRow {
+    /// The file line whose text the row shows
+    fn text_line(&self) -> Option<usize> {
+        match *self {
+            DisplayRow::Line(line_num) => Some(line_num),
+            DisplayRow::Fold(block) => Some(block.start),
+            DisplayRow::Expansion { .. } => None,
+        }
+    }
+}
+
+/// Input prompts shown in place of the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    Search,
+    Goto,
+    Query,
+    Column,
+    /// Path to save the selection to
+    Save,
+    /// Shell command to pipe the selection to
+    Pipe,
+}
+
+impl PromptKind {
+    fn label(&self) -> &'static str {
+        match self {
+            PromptKind::Search => "/",
+            PromptKind::Goto => ":",
+            PromptKind::Query => "query: ",
+            PromptKind::Column => "column: ",
+            PromptKind::Save => "save to: ",
+            PromptKind::Pipe => "pipe to: ",
         }
     }
 }
 
+/// A mark command waiting for the mark name, e.g. after `m` or `'`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkAction {
+    Set,
+    Jump,
+}
+
+/// A search or indexing job running on a background thread
+struct BackgroundTask {
+    id: u64,
+    cancel: Arc<AtomicBool>,
+}
+
 pub enum ViewerAction {
     None,
     Quit,
+    /// Show what a piped command printed in a viewer of its own
+    ShowOutput {
+        command: String,
+        file_reader: FileReader,
+    },
 }
 
 pub struct Viewer {
     file_reader: FileReader,
+    alternate_reader: Option<FileReader>,
+    line_map: Option<LineMap>,
+    showing_formatted: bool,
+    format_task: Option<BackgroundTask>,
+    format_kind: Option<FormatKind>,
+    /// Where the formatted copy is written instead of the cache, if anywhere
+    format_output: Option<PathBuf>,
+    /// Why the formatted copy stops short of the end of the file, if it does
+    format_error: Option<SyntaxError>,
+    syntax: Option<SyntaxKind>,
+    /// Set while delimited lines are drawn as aligned columns
+    table: Option<TableView>,
     current_line: usize,
-    search_matches: Vec<usize>,
+    top_sub_row: usize,
+    /// The text cursor as (line, column). The column is where the cursor wants to be, and is
+    /// pulled back onto shorter lines when used.
+    cursor: (usize, usize),
+    /// The keyboard selection mode, if one is on
+    visual: Option<VisualMode>,
+    /// Where the cursor was when the visual mode started
+    visual_anchor: (usize, usize),
+    search_matches: Vec<SearchMatch>,
     current_match: usize,
-    in_search_mode: bool,
+    prompt: Option<PromptKind>,
     viewport_height: usize,
     selection: Option<Selection>,
     selecting: bool,
+    /// Where the mouse was last dragged to, in screen coordinates
+    drag_position: (u16, u16),
+    /// Scrolls the view while a selection is dragged above or below it
+    drag_scroll_task: Option<BackgroundTask>,
+    /// When and where the last click was, and how many clicks in a row it made
+    last_click: Option<(Instant, (u16, u16), u8)>,
+    /// Characters besides letters, digits and `_` that double-click takes as part of a word
+    word_chars: String,
     context_menu: Option<ContextMenu>,
+    /// The size of the last frame, which the context menu is placed within
+    screen: Rect,
+    clipboard: ClipboardBackend,
     progress_visible: bool,
     progress_value: f64,
     progress_message: String,
-    search_requested: bool,
-    search_cancelled: bool,
+    events: Sender<AppEvent>,
+    search_task: Option<BackgroundTask>,
+    /// The command the selection is being piped to
+    command_task: Option<BackgroundTask>,
+    next_task_id: u64,
     last_search_term: String,
+    /// The prompt the current matches came from, either a text search or a query
+    search_source: PromptKind,
+    /// Show only the lines with search matches
+    filtering: bool,
+    /// Text marked wherever it occurs, independently of the search
+    highlights: Vec<String>,
     search_textarea: TextArea<'static>,
+    prompt_textarea: TextArea<'static>,
+    query_textarea: TextArea<'static>,
+    pending_count: Option<usize>,
+    pending_mark: Option<MarkAction>,
+    marks: Marks,
+    jump_list: JumpList,
+    expansions: RecordExpansions,
+    structure: Option<Arc<StructureIndex>>,
+    structure_task: Option<BackgroundTask>,
+    folds: BTreeMap<usize, StructureBlock>,
+    /// The folds that were closed when the filter was turned on, closed again when it is cleared
+    folds_before_filter: BTreeMap<usize, StructureBlock>,
+    /// The status bar path of the focus line, and the line it belongs to
+    line_path: Option<(usize, String)>,
+    pending_fold: bool,
+    visible_rows: Vec<DisplayRow>,
+    status_message: Option<String>,
+    search_generation: u64,
+    line_layouts: HashMap<usize, LineLayout>,
+    layout_key: Option<LayoutKey>,
+    gutter_buf: String,
 }
 
 impl Viewer {
-    pub fn new(file_reader: FileReader) -> Self {
-        let mut search_textarea = TextArea::default();
-        search_textarea.set_cursor_line_style(Style::default());
-        search_textarea.set_style(Style::default().bg(ratatui::style::Color::Blue));
-        search_textarea.set_line_number_style(Style::default());
-        search_textarea.remove_line_number();
-        
+    pub fn new(file_reader: FileReader, events: Sender<AppEvent>) -> Self {
         Self {
             file_reader,
+            alternate_reader: None,
+            line_map: None,
+            showing_formatted: false,
+            format_task: None,
+            format_kind: None,
+            format_output: None,
+            format_error: None,
+            syntax: None,
+            table: None,
             current_line: 0,
+            top_sub_row: 0,
+            cursor: (0, 0),
+            visual: None,
+            visual_anchor: (0, 0),
             search_matches: Vec::new(),
             current_match: 0,
-            in_search_mode: false,
+            prompt: None,
             viewport_height: Constants::DEFAULT_VIEWPORT_HEIGHT,
             selection: None,
             selecting: false,
+            drag_position: (0, 0),
+            drag_scroll_task: None,
+            last_click: None,
+            word_chars: Constants::DEFAULT_WORD_CHARS.to_string(),
             context_menu: None,
+            screen: Rect::default(),
+            clipboard: ClipboardBackend::System,
             progress_visible: false,
             progress_value: 0.0,
             progress_message: String::new(),
-            search_requested: false,
-            search_cancelled: false,
+            events,
+            search_task: None,
+            command_task: None,
+            next_task_id: 0,
             last_search_term: String::new(),
-            search_textarea,
+            search_source: PromptKind::Search,
+            filtering: false,
+            highlights: Vec::new(),
+            search_textarea: Self::new_prompt_textarea(),
+            prompt_textarea: Self::new_prompt_textarea(),
+            query_textarea: Self::new_prompt_textarea(),
+            pending_count: None,
+            pending_mark: None,
+            marks: Marks::default(),
+            jump_list: JumpList::default(),
+            expansions: RecordExpansions::default(),
+            structure: None,
+            structure_task: None,
+            folds: BTreeMap::new(),
+            folds_before_filter: BTreeMap::new(),
+            line_path: None,
+            pending_fold: false,
+            visible_rows: Vec::new(),
+            status_message: None,
+            search_generation: 0,
+            line_layouts: HashMap::new(),
+            layout_key: None,
+            gutter_buf: String::new(),
         }
     }
     
-    pub fn new_empty() -> Self {
+    pub fn new_empty(events: Sender<AppEvent>) -> Self {
         // Create a minimal empty FileReader for progress display
         let empty_file_reader = FileReader::new_empty().unwrap_or_else(|_| {
             // Fallback - this shouldn't happen but just in case
             FileReader::new_with_progress(".", None).unwrap()
         });
         
-        let mut search_textarea = TextArea::default();
-        search_textarea.set_cursor_line_style(Style::default());
-        search_textarea.set_style(Style::default().bg(ratatui::style::Color::Blue));
-        search_textarea.set_line_number_style(Style::default());
-        search_textarea.remove_line_number();
+        Self::new(empty_file_reader, events)
+    }
+    
+    fn new_prompt_textarea() -> TextArea<'static> {
+        let mut textarea = TextArea::default();
+        textarea.set_cursor_line_style(Style::default());
+        textarea.set_style(Style::default().bg(ratatui::style::Color::Blue));
+        textarea.set_line_number_style(Style::default());
+        textarea.remove_line_number();
+        textarea
+    }
+    
+    /// Swap in the file once it has finished loading in the background
+    pub fn set_file_reader(&mut self, file_reader: FileReader) {
+        self.file_reader = file_reader;
+        self.expansions.clear();
+        self.set_top_line(0);
+        self.cursor = (0, 0);
+        self.line_layouts.clear();
+        self.hide_progress();
+        self.request_structure_index();
+    }
+    
+    /// Pick up changes after the file was modified on disk
+    pub fn reload_file(&mut self) {
+        if self.showing_formatted {
+            // The formatted copy is a snapshot, so only the raw file behind it is refreshed
+            if let Some(Ok(FileChange::Appended | FileChange::Replaced)) = self.alternate_reader.as_mut().map(FileReader::refresh) {
+                self.set_status_message("File changed on disk, the formatted view is out of date");
+            }
+            return;
+        }
         
-        Self {
-            file_reader: empty_file_reader,
-            current_line: 0,
-            search_matches: Vec::new(),
-            current_match: 0,
-            in_search_mode: false,
-            viewport_height: Constants::DEFAULT_VIEWPORT_HEIGHT,
-            selection: None,
-            selecting: false,
-            context_menu: None,
-            progress_visible: false,
-            progress_value: 0.0,
-            progress_message: String::new(),
-            search_requested: false,
-            search_cancelled: false,
-            last_search_term: String::new(),
-            search_textarea,
+        let Ok(change) = self.file_reader.refresh() else {
+            return;
+        };
+        if change == FileChange::Replaced {
+            // Matches, expanded records, folds and the selection all point into the old content
+            self.expansions.clear();
+            self.folds.clear();
+            self.top_sub_row = 0;
+            self.clear_selection();
+            self.rerun_search();
+        }
+        if change != FileChange::Unchanged {
+            self.line_layouts.clear();
+            let max_line = self.file_reader.line_count().saturating_sub(self.viewport_height);
+            if self.current_line > max_line {
+                self.set_top_line(max_line);
+            }
+            
+            // Reformat on the next toggle rather than showing a stale copy
+            self.alternate_reader = None;
+            self.line_map = None;
+            self.format_error = None;
+            self.request_structure_index();
         }
     }
     
-    pub fn draw(&mut self, f: &mut Frame) {
-        let chunks = if self.progress_visible {
-            Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(0),
-                    Constraint::Length(Constants::PROGRESS_BAR_HEIGHT),
-                    Constraint::Length(1),
-                ])
-                .split(f.size())
-        } else {
-            Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Min(0), Constraint::Length(1)])
-                .split(f.size())
+    // Raw and formatted views
+    /// Switch between the raw file and its formatted copy, formatting it first if necessary
+    pub fn toggle_formatted_view(&mut self) {
+        if self.format_task.is_some() {
+            return;
+        }
+        
+        let (other_reader, line_map) = match (self.alternate_reader.take(), self.line_map.as_ref()) {
+            (Some(other_reader), Some(line_map)) => (other_reader, line_map),
+            (other_reader, _) => {
+                // Put back whichever reader was taken, so a copy without its line map isn't lost
+                self.alternate_reader = other_reader;
+                self.request_formatting();
+                return;
+            }
+        };
+        
+        // Formatted lines map to byte offsets in the raw file, which is how positions carry over
+        let to_formatted = !self.showing_formatted;
+        let raw_reader = if to_formatted { &self.file_reader } else { &other_reader };
+        let translate = |line: usize| {
+            if to_formatted {
+                line_map.line_for_source_offset(raw_reader.line_start(line) as u64)
+            } else {
+                raw_reader.line_for_offset(line_map.source_offset(line) as usize)
+            }
+        };
+        let target_line = translate(self.current_line);
+        let cursor_line = translate(self.cursor.0);
+        self.marks.remap(translate);
+        self.jump_list.remap(translate);
+        self.expansions.clear();
+        self.folds.clear();
+        self.folds_before_filter.clear();
+        
+        let previous_reader = std::mem::replace(&mut self.file_reader, other_reader);
+        self.alternate_reader = Some(previous_reader);
+        self.showing_formatted = to_formatted;
+        self.scroll_to(target_line);
+        self.cursor = (cursor_line, 0);
+        
+        self.clear_selection();
+        self.line_layouts.clear();
+        self.rerun_search();
+        self.request_structure_index();
+        self.set_status_message(if to_formatted { "Formatted view" } else { "Raw view" });
+    }
+    
+    /// The format used when the formatted view is first requested
+    /// Set the file's format. A delimited format starts in the table view if `open_table` is set.
+    pub fn set_format_kind(&mut self, format_kind: Option<FormatKind>, open_table: bool) {
+        self.format_kind = format_kind;
+        self.syntax = format_kind.and_then(SyntaxKind::for_format);
+        self.table = format_kind.and_then(|kind| kind.delimiter()).filter(|_| open_table).map(TableView::new);
+        self.line_layouts.clear();
+    }
+    
+    pub fn set_clipboard(&mut self, clipboard: ClipboardBackend) {
+        self.clipboard = clipboard;
+    }
+    
+    pub fn set_word_chars(&mut self, word_chars: String) {
+        self.word_chars = word_chars;
+    }
+    
+    pub fn set_format_output(&mut self, format_output: Option<PathBuf>) {
+        self.format_output = format_output;
+    }
+    
+    /// Format the raw file on a background thread
+    fn request_formatting(&mut self) {
+        let kind = match self.format_kind {
+            Some(FormatKind::JsonLines) => {
+                self.set_status_message("JSON Lines records are expanded one at a time with e");
+                return;
+            }
+            Some(FormatKind::Csv | FormatKind::Tsv) => {
+                self.set_status_message("Delimited files are shown as a table, toggle it with t");
+                return;
+            }
+            Some(kind) => kind,
+            None => {
+                self.set_status_message("No format to apply, choose one with --format");
+                return;
+            }
         };
+        let path = self.file_reader.path().to_path_buf();
+        let output = self.format_output.clone();
+        
+        self.next_task_id += 1;
+        let task_id = self.next_task_id;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.format_task = Some(BackgroundTask { id: task_id, cancel: cancel.clone() });
+        
+        self.show_progress(0.0, "Formatting...");
+        let progress_callback = app_event::progress_sender(&self.events, Some(task_id));
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            let result = FileFormatter::format_file(&path, kind, output.as_deref(), Some(progress_callback), task_id, &cancel)
+                .and_then(|formatted| formatted.open(None));
+            let _ = events.send(AppEvent::Formatted { task_id, result });
+        });
+    }
+    
+    pub fn is_formatting(&self) -> bool {
+        self.format_task.is_some()
+    }
+    
+    /// Stop formatting and stay on the raw file. The thread stops at its next check, and
+    /// whatever it sends back is ignored.
+    pub fn cancel_formatting(&mut self) {
+        if let Some(task) = self.format_task.take() {
+            task.cancel.store(true, Ordering::Relaxed);
+            self.hide_progress();
+            self.set_status_message("Formatting cancelled, showing the raw file");
+        }
+    }
+    
+    /// Keep the formatted copy alongside the raw file and switch to it
+    pub fn finish_formatting(&mut self, task_id: u64, result: anyhow::Result<FormattedSource>) {
+        if self.format_task.as_ref().map(|task| task.id) != Some(task_id) {
+            return;
+        }
+        self.format_task = None;
+        self.hide_progress();
+        
+        match result {
+            Ok(source) => {
+                if self.showing_formatted {
+                    // A fresh copy replaces the one on screen, so go back to raw first
+                    self.toggle_formatted_view();
+                }
+                self.alternate_reader = Some(source.reader);
+                self.line_map = Some(source.line_map);
+                self.format_error = source.error;
+                
+                // A partial copy is only shown on request, so malformed input opens at the error
+                match self.format_error {
+                    Some(ref error) => {
+                        let error_line = self.file_reader.line_for_offset(error.offset as usize);
+                        self.jump_to_line(error_line);
+                    }
+                    None => self.toggle_formatted_view(),
+                }
+            }
+            Err(e) => self.set_status_message(format!("Formatting failed, showing the raw file: {:#}", e)),
+        }
+    }
+    
+    /// Search the newly shown source for the last search term, since match lines don't carry over.
+    /// Query results are dropped instead, as the new source has to be indexed before querying.
+    fn rerun_search(&mut self) {
+        self.stop_search_task();
+        self.search_matches.clear();
+        self.current_match = 0;
+        self.search_generation += 1;
+        if self.search_source == PromptKind::Query {
+            self.last_search_term.clear();
+        } else if !self.last_search_term.is_empty() {
+            self.request_search();
+        }
+    }
+    
+    pub fn draw(&mut self, f: &mut Frame) {
+        // Optional rows sit below the content, so mouse rows map to text the same way with or without them
+        let banner_height = if self.format_error.is_some() { 1 } else { 0 };
+        let progress_height = if self.progress_visible { Constants::PROGRESS_BAR_HEIGHT } else { 0 };
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(banner_height),
+                Constraint::Length(progress_height),
+                Constraint::Length(1),
+            ])
+            .split(f.size());
+        self.screen = f.size();
 
-        self.viewport_height = chunks[0].height as usize;
+        // The content block has a border on each side, so two rows are not available for text
+        self.viewport_height = chunks[0].height.saturating_sub(2) as usize;
+        if self.table.is_some() {
+            // The header row stays on screen in place of a line
+            self.viewport_height = self.viewport_height.saturating_sub(1);
+        }
+        self.keep_cursor_in_viewport();
         
         self.draw_content(f, chunks[0]);
+        self.update_line_path();
         
         if let Some(ref menu) = self.context_menu {
             self.draw_context_menu(f, menu);
         }
         
+        if let Some(ref error) = self.format_error {
+            self.draw_format_error_banner(f, chunks[1], error);
+        }
         if self.progress_visible {
-            self.draw_progress_bar(f, chunks[1]);
-            self.draw_status_bar(f, chunks[2]);
-        } else {
-            self.draw_status_bar(f, chunks[1]);
+            self.draw_progress_bar(f, chunks[2]);
         }
+        self.draw_status_bar(f, chunks[3]);
     }
     
     // State queries
-    pub fn is_in_search_mode(&self) -> bool {
-        self.in_search_mode
+    pub fn is_in_prompt(&self) -> bool {
+        self.prompt.is_some()
     }
     
     pub fn has_context_menu(&self) -> bool {
         self.context_menu.is_some()
     }
     
-    pub fn has_search_requested(&self) -> bool {
-        self.search_requested
+    /// Whether a running background task with this id was started by this viewer
+    pub fn owns_task(&self, task_id: u64) -> bool {
+        [&self.format_task, &self.search_task, &self.structure_task, &self.command_task, &self.drag_scroll_task]
+            .into_iter()
+            .flatten()
+            .any(|task| task.id == task_id)
     }
     
+    pub fn is_search_running(&self) -> bool {
+        self.search_task.is_some()
+    }
+    
+    /// Start searching for the current search term on a background thread
     pub fn request_search(&mut self) {
-        self.search_requested = true;
-        self.search_cancelled = false;
+        self.stop_search_task();
+        self.search_source = PromptKind::Search;
+        
+        let search_term = self.get_search_term().to_string();
+        if search_term.is_empty() {
+            self.set_search_results(Vec::new(), search_term);
+            return;
+        }
+        
+        self.next_task_id += 1;
+        let task_id = self.next_task_id;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.search_task = Some(BackgroundTask { id: task_id, cancel: cancel.clone() });
+        
+        // Only show progress for searches that might take a while
+        let progress_callback = if self.file_reader.line_count() > Constants::SEARCH_PROGRESS_MIN_LINES {
+            self.show_progress(0.0, "Searching... (ESC to cancel)");
+            Some(app_event::progress_sender(&self.events, Some(task_id)))
+        } else {
+            None
+        };
+        
+        let file_reader = self.file_reader.clone();
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            let matches = file_reader.search_with_progress(&search_term, progress_callback, &cancel);
+            if !cancel.load(Ordering::Relaxed) {
+                let _ = events.send(AppEvent::SearchComplete { task_id, search_term, matches });
+            }
+        });
+    }
+    
+    /// Resolve the query in the query prompt against the document structure on a background thread
+    fn request_query(&mut self) {
+        self.stop_search_task();
+        
+        let expression = self.query_textarea.lines()[0].trim().to_string();
+        if expression.is_empty() {
+            return;
+        }
+        let Some(syntax) = self.syntax else {
+            self.set_status_message("Queries need a JSON or XML document");
+            return;
+        };
+        let query = match DocumentQuery::parse(syntax, &expression) {
+            Ok(query) => query,
+            Err(e) => {
+                self.set_status_message(format!("Invalid query: {:#}", e));
+                return;
+            }
+        };
+        if self.structure_or_explain().is_none() {
+            return;
+        }
+        let Some(structure) = self.structure.clone() else {
+            return;
+        };
+        if structure.root().is_none() {
+            // A minified document sits on one line, so it has no structure to walk
+            self.set_status_message("Queries need a pretty-printed document, press f to format it");
+            return;
+        }
+        
+        self.search_source = PromptKind::Query;
+        self.next_task_id += 1;
+        let task_id = self.next_task_id;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.search_task = Some(BackgroundTask { id: task_id, cancel: cancel.clone() });
+        
+        let file_reader = self.file_reader.clone();
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            if let Some(matches) = query.evaluate(&structure, &file_reader, &cancel) {
+                let _ = events.send(AppEvent::SearchComplete { task_id, search_term: expression, matches });
+            }
+        });
+    }
+    
+    pub fn finish_search(&mut self, task_id: u64, search_term: String, matches: Vec<SearchMatch>) {
+        // Results from a search that has since been cancelled or replaced are stale
+        if self.search_task.as_ref().map(|task| task.id) != Some(task_id) {
+            return;
+        }
+        
+        self.search_task = None;
+        self.set_search_results(matches, search_term);
+        self.hide_progress();
+    }
+    
+    /// Stop a running search or query and return to its prompt with the input preserved
+    pub fn cancel_search(&mut self) {
+        if self.stop_search_task() {
+            self.prompt = Some(self.search_source);
+        }
+    }
+    
+    fn stop_search_task(&mut self) -> bool {
+        match self.search_task.take() {
+            Some(task) => {
+                task.cancel.store(true, Ordering::Relaxed);
+                self.hide_progress();
+                true
+            }
+            None => false,
+        }
     }
     
+    /// Drop the search along with the filter and highlights made from it
     pub fn clear_search(&mut self) {
+        self.stop_search_task();
+        if self.filtering {
+            self.filtering = false;
+            self.folds = std::mem::take(&mut self.folds_before_filter);
+            self.set_top_line(self.current_line);
+        }
+        self.highlights.clear();
         self.search_textarea.delete_line_by_head();
         self.search_textarea.delete_line_by_end();
         self.search_matches.clear();
+        self.search_generation += 1;
         self.current_match = 0;
-        self.search_requested = false;
-        self.search_cancelled = false;
         self.last_search_term.clear();
     }
     
     // Progress bar operations
+    pub fn update_progress(&mut self, value: f64, message: &str) {
+        // Late updates from a task that was already cancelled or finished are ignored
+        if self.progress_visible {
+            self.show_progress(value, message);
+        }
+    }
+    
     pub fn show_progress(&mut self, value: f64, message: &str) {
         self.progress_visible = true;
         self.progress_value = value;
@@ -193,164 +700,138 @@ impl Viewer {
         self.progress_message.clear();
     }
     
-    // Search operations
+    // Status messages
+    pub fn set_status_message(&mut self, message: impl Into<String>) {
+        self.status_message = Some(message.into());
+    }
+    
+    pub fn clear_status_message(&mut self) {
+        self.status_message = None;
+    }
+    
+    // Prompt operations
     pub fn enter_search_mode(&mut self) {
-        self.in_search_mode = true;
+        self.prompt = Some(PromptKind::Search);
         self.search_textarea.delete_line_by_head();
         self.search_textarea.delete_line_by_end();
     }
     
-    pub fn exit_search_mode(&mut self) {
-        self.in_search_mode = false;
+    pub fn enter_goto_mode(&mut self) {
+        self.prompt = Some(PromptKind::Goto);
+        self.prompt_textarea.delete_line_by_head();
+        self.prompt_textarea.delete_line_by_end();
+    }
+    
+    /// Open the prompt for a column number or name to scroll the table to
+    pub fn enter_column_mode(&mut self) {
+        if self.table_or_explain().is_none() {
+            return;
+        }
+        self.prompt = Some(PromptKind::Column);
+        self.prompt_textarea.delete_line_by_head();
+        self.prompt_textarea.delete_line_by_end();
     }
     
-    pub fn get_search_term(&self) -> String {
-        self.search_textarea.lines()[0].clone()
+    /// Open the query prompt, keeping the last query so it can be refined
+    pub fn enter_query_mode(&mut self) {
+        self.prompt = Some(PromptKind::Query);
     }
     
-    pub fn handle_search_input(&mut self, key: crossterm::event::KeyEvent) -> bool {
-        // Handle Ctrl+V for paste
-        if key.code == crossterm::event::KeyCode::Char('v') && key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) {
-            if let Ok(mut ctx) = clipboard::ClipboardContext::new() {
-                if let Ok(content) = ctx.get_contents() {
-                    // Only paste if clipboard contains text (no newlines)
-                    if !content.contains('\n') && !content.contains('\r') {
-                        self.search_textarea.insert_str(&content);
-                        return true;
+    pub fn exit_prompt(&mut self) {
+        self.prompt = None;
+    }
+    
+    /// Act on the text entered into the active prompt and close it
+    pub fn submit_prompt(&mut self) {
+        match self.prompt.take() {
+            Some(PromptKind::Search) => self.request_search(),
+            Some(PromptKind::Query) => self.request_query(),
+            Some(PromptKind::Goto) => {
+                let input = self.prompt_textarea.lines()[0].clone();
+                match GotoTarget::parse(&input) {
+                    Ok(target) => {
+                        let line = target.resolve(&self.file_reader);
+                        self.jump_to_line(line);
                     }
+                    Err(e) => self.set_status_message(e.to_string()),
                 }
             }
+            Some(PromptKind::Column) => {
+                let input = self.prompt_textarea.lines()[0].clone();
+                self.jump_to_named_column(&input);
+            }
+            Some(PromptKind::Save) => {
+                let input = self.prompt_textarea.lines()[0].clone();
+                self.save_selection(input.trim());
+            }
+            Some(PromptKind::Pipe) => {
+                let input = self.prompt_textarea.lines()[0].clone();
+                self.pipe_selection(input.trim());
+            }
+            None => {}
+        }
+    }
+    
+    fn active_textarea(&mut self) -> &mut TextArea<'static> {
+        match self.prompt {
+            Some(PromptKind::Search) | None => &mut self.search_textarea,
+            Some(PromptKind::Goto | PromptKind::Column | PromptKind::Save | PromptKind::Pipe) => &mut self.prompt_textarea,
+            Some(PromptKind::Query) => &mut self.query_textarea,
+        }
+    }
+    
+    pub fn get_search_term(&self) -> &str {
+        &self.search_textarea.lines()[0]
+    }
+    
+    pub fn handle_prompt_input(&mut self, key: crossterm::event::KeyEvent) -> bool {
+        // Handle Ctrl+V for paste
+        if key.code == crossterm::event::KeyCode::Char('v') && key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) {
+            if let Ok(content) = self.clipboard.paste() {
+                return self.paste(&content);
+            }
         }
         
         // Let TextArea handle all other input
-        self.search_textarea.input(key)
+        self.active_textarea().input(key)
     }
     
-    pub fn perform_search_with_progress(&mut self) {
-        let search_term = self.get_search_term();
-        if search_term.is_empty() {
-            self.search_matches.clear();
-            return;
+    /// Insert pasted text into the prompt, which only takes a single line
+    pub fn paste(&mut self, text: &str) -> bool {
+        let text = text.trim_end_matches(['\r', '\n']);
+        if self.prompt.is_none() || text.contains(['\r', '\n']) {
+            return false;
         }
-        
-        // Show initial progress
-        self.show_progress(0.0, "Searching... (ESC to cancel)");
-        
-        // Perform search with progress tracking
-        self.last_search_term = search_term.clone();
-        self.search_matches = self.file_reader.search_with_progress(&search_term, None);
+        self.active_textarea().insert_str(text);
+        true
+    }
+    
+    fn set_search_results(&mut self, matches: Vec<SearchMatch>, search_term: String) {
+        self.search_matches = matches;
+        self.search_generation += 1;
         self.current_match = 0;
+        self.last_search_term = search_term;
         
         if !self.search_matches.is_empty() {
-            self.current_line = self.search_matches[0].saturating_sub(self.viewport_height / 2);
+            self.center_on_current_match();
         }
-        
-        self.hide_progress();
     }
     
-    pub fn perform_search_with_ui_progress(&mut self, terminal: &mut ratatui::Terminal<ratatui::backend::CrosstermBackend<std::io::Stdout>>) -> Result<(), std::io::Error> {
-        let search_term = self.get_search_term();
-        if search_term.is_empty() {
-            self.search_matches.clear();
-            self.search_requested = false;
-            return Ok(());
-        }
-        
-        // Only show progress for searches that might take a while
-        let total_lines = self.file_reader.line_count();
-        if total_lines > 100_000 { // Show progress for files with more than 100k lines
-            // Create a channel for progress updates
-            let (progress_tx, progress_rx) = std::sync::mpsc::channel();
-            let search_term_for_thread = search_term.clone();
-            
-            // Create a thread-safe search context
-            let search_context = self.file_reader.create_search_context();
-            
-            let search_thread = std::thread::spawn(move || {
-                let progress_callback: crate::file_reader::ProgressCallback = Box::new(move |progress, message| {
-                    let _ = progress_tx.send((progress, message.to_string()));
-                });
-                
-                search_context.search_with_progress(&search_term_for_thread, Some(progress_callback))
-            });
-            
-            // Show initial progress
-            self.show_progress(0.0, "Searching...");
-            
-            // Update progress bar while searching
-            let mut last_update = std::time::Instant::now();
-            loop {
-                // Check for keyboard input to allow cancellation
-                if crossterm::event::poll(std::time::Duration::from_millis(10))? {
-                    if let crossterm::event::Event::Key(key) = crossterm::event::read()? {
-                        if key.code == crossterm::event::KeyCode::Esc {
-                            // Cancel search and return to search mode with preserved text
-                            self.search_cancelled = true;
-                            self.in_search_mode = true;  // Enter search mode without clearing text
-                            self.hide_progress();
-                            self.search_requested = false;
-                            return Ok(());
-                        }
-                    }
-                }
-                
-                // Check for progress updates
-                match progress_rx.try_recv() {
-                    Ok((progress, message)) => {
-                        self.show_progress(progress, &message);
-                        
-                        // Update UI every 200ms to reduce overhead
-                        if last_update.elapsed() >= std::time::Duration::from_millis(200) {
-                            terminal.draw(|f| self.draw(f))?;
-                            last_update = std::time::Instant::now();
-                        }
-                        
-                        if progress >= 1.0 {
-                            // Final update
-                            terminal.draw(|f| self.draw(f))?;
-                            break;
-                        }
-                    }
-                    Err(std::sync::mpsc::TryRecvError::Empty) => {
-                        // No progress update, continue
-                    }
-                    Err(std::sync::mpsc::TryRecvError::Disconnected) => {
-                        // Search thread finished
-                        break;
-                    }
-                }
-                
-                // Small delay to prevent excessive CPU usage
-                std::thread::sleep(std::time::Duration::from_millis(50));
-            }
-            
-            // Wait for search thread to complete and get result
-            match search_thread.join() {
-                Ok(results) => {
-                    self.search_matches = results;
-                    self.current_match = 0;
-                    self.last_search_term = search_term.clone();
-                    
-                    if !self.search_matches.is_empty() {
-                        self.current_line = self.search_matches[0].saturating_sub(self.viewport_height / 2);
-                    }
-                    
-                    self.hide_progress();
-                    self.search_requested = false;
-                    Ok(())
-                }
-                Err(_) => {
-                    self.hide_progress();
-                    self.search_requested = false;
-                    Err(std::io::Error::new(std::io::ErrorKind::Other, "Search thread panicked"))
-                }
-            }
-        } else {
-            // Small file, search directly without progress
-            self.perform_search_with_progress();
-            self.search_requested = false;
-            Ok(())
-        }
+    fn center_on_current_match(&mut self) {
+        let from = self.current_line;
+        let search_match = &self.search_matches[self.current_match];
+        let target = (search_match.line, search_match.start);
+        self.reveal_line(target.0);
+        self.set_top_line(target.0.saturating_sub(self.viewport_height / 2));
+        self.move_cursor_to(target);
+        self.remember_jump(from);
+    }
+    
+    /// Index range into `search_matches` covering the matches on a single line
+    fn matches_on_line(&self, line_num: usize) -> std::ops::Range<usize> {
+        let start = self.search_matches.partition_point(|m| m.line < line_num);
+        let end = start + self.search_matches[start..].partition_point(|m| m.line == line_num);
+        start..end
     }
     
     pub fn next_match(&mut self) {
@@ -359,8 +840,7 @@ impl Viewer {
         }
         
         self.current_match = (self.current_match + 1) % self.search_matches.len();
-        let target_line = self.search_matches[self.current_match];
-        self.current_line = target_line.saturating_sub(self.viewport_height / 2);
+        self.center_on_current_match();
     }
     
     pub fn prev_match(&mut self) {
@@ -374,20 +854,75 @@ impl Viewer {
             self.current_match - 1
         };
         
-        let target_line = self.search_matches[self.current_match];
-        self.current_line = target_line.saturating_sub(self.viewport_height / 2);
+        self.center_on_current_match();
     }
     
     // Navigation operations
+    /// Scroll by one screen row, stepping through the rows of expanded records
     pub fn scroll_up(&mut self) {
-        self.current_line = self.current_line.saturating_sub(1);
+        if self.top_sub_row > 0 {
+            self.top_sub_row -= 1;
+        } else if let Some(line) = self.prev_visible_line(self.current_line) {
+            self.current_line = line;
+            self.top_sub_row = self.row_count(line) - 1;
+        }
     }
     
     pub fn scroll_down(&mut self) {
-        let max_line = self.file_reader.line_count().saturating_sub(self.viewport_height);
-        if self.current_line < max_line {
-            self.current_line += 1;
+        if !self.has_rows_below_viewport() {
+            return;
+        }
+        
+        if self.top_sub_row + 1 < self.row_count(self.current_line) {
+            self.top_sub_row += 1;
+        } else {
+            self.current_line = self.next_visible_line(self.current_line);
+            self.top_sub_row = 0;
+        }
+    }
+    
+    /// Whether the end of the file is still below the bottom of the viewport
+    fn has_rows_below_viewport(&self) -> bool {
+        let line_count = self.file_reader.line_count();
+        let mut rows = self.row_count(self.current_line) - self.top_sub_row;
+        let mut line_num = self.next_visible_line(self.current_line);
+        while rows <= self.viewport_height && line_num < line_count {
+            rows += self.row_count(line_num);
+            line_num = self.next_visible_line(line_num);
+        }
+        rows > self.viewport_height
+    }
+    
+    /// Number of screen rows a line takes up
+    fn row_count(&self, line_num: usize) -> usize {
+        if self.folds.contains_key(&line_num) {
+            1
+        } else {
+            self.expansions.row_count(line_num)
+        }
+    }
+    
+    /// The line shown after `line_num`, skipping over the rest of a closed fold, or while
+    /// filtering, over the lines without matches
+    fn next_visible_line(&self, line_num: usize) -> usize {
+        if self.is_filtered() {
+            let index = self.search_matches.partition_point(|m| m.line <= line_num);
+            return self.search_matches.get(index).map_or(self.file_reader.line_count(), |m| m.line);
+        }
+        match self.folds.get(&line_num) {
+            Some(block) => block.end + 1,
+            None => line_num + 1,
+        }
+    }
+    
+    /// The line shown before `line_num`, which is the start of a fold if one hides it
+    fn prev_visible_line(&self, line_num: usize) -> Option<usize> {
+        if self.is_filtered() {
+            let index = self.search_matches.partition_point(|m| m.line < line_num);
+            return index.checked_sub(1).map(|index| self.search_matches[index].line);
         }
+        let previous = line_num.checked_sub(1)?;
+        Some(self.closed_fold_at(previous).map_or(previous, |block| block.start))
     }
     
     pub fn scroll_up_multiple(&mut self, count: usize) {
@@ -402,52 +937,734 @@ impl Viewer {
         }
     }
     
+    /// Scroll by a screen, taking the cursor along so a visual selection grows page by page.
+    /// Once the viewport can't move, the cursor goes to the first or last line instead.
     pub fn page_up(&mut self) {
-        self.current_line = self.current_line.saturating_sub(self.viewport_height);
+        let top = (self.current_line, self.top_sub_row);
+        self.scroll_up_multiple(self.viewport_height);
+        let line = if (self.current_line, self.top_sub_row) == top {
+            0
+        } else {
+            self.cursor.0.saturating_sub(top.0 - self.current_line)
+        };
+        self.move_cursor_to((line, self.cursor.1));
     }
     
     pub fn page_down(&mut self) {
-        let max_line = self.file_reader.line_count().saturating_sub(self.viewport_height);
-        self.current_line = (self.current_line + self.viewport_height).min(max_line);
+        let top = (self.current_line, self.top_sub_row);
+        self.scroll_down_multiple(self.viewport_height);
+        let line = if (self.current_line, self.top_sub_row) == top {
+            self.file_reader.line_count().saturating_sub(1)
+        } else {
+            self.cursor.0 + (self.current_line - top.0)
+        };
+        self.move_cursor_to((line, self.cursor.1));
     }
     
-    pub fn goto_start(&mut self) {
-        self.current_line = 0;
+    /// Put a line at the top of the viewport, starting from its first row. A line hidden
+    /// in a closed fold brings the fold to the top instead.
+    fn set_top_line(&mut self, line: usize) {
+        self.current_line = self.shown_line(line);
+        self.top_sub_row = 0;
     }
     
-    pub fn goto_end(&mut self) {
-        let total_lines = self.file_reader.line_count();
-        if total_lines <= self.viewport_height {
-            // File fits entirely in viewport, start from beginning
-            self.current_line = 0;
-        } else {
-            // Position so the last line appears at the bottom of viewport
-            self.current_line = total_lines - self.viewport_height;
+    /// The line shown in place of `line`: the line itself, the start of a closed fold hiding
+    /// it, or while filtering, the next line with a match, or the last one
+    fn shown_line(&self, line: usize) -> usize {
+        if self.is_filtered() {
+            let index = self.search_matches.partition_point(|m| m.line < line).min(self.search_matches.len() - 1);
+            return self.search_matches[index].line;
         }
+        self.closed_fold_at(line).map_or(line, |block| block.start)
     }
     
-    // Selection operations
-    pub fn start_selection(&mut self, col: u16, row: u16) {
-        self.context_menu = None;
-        
-        if let Some((line, column)) = self.screen_to_text_coords(col, row) {
-            self.selection = Some(Selection::new(line, column));
-            self.selecting = true;
-        }
+    /// Whether only the lines with search matches are shown
+    fn is_filtered(&self) -> bool {
+        self.filtering && !self.search_matches.is_empty()
     }
     
-    pub fn update_selection(&mut self, col: u16, row: u16) {
-        if self.selecting {
-            if let Some((line, column)) = self.screen_to_text_coords(col, row) {
-                if let Some(ref mut selection) = self.selection {
-                    selection.update_end(line, column);
+    /// Bring a line to the top of the viewport, as far as the end of the file allows
+    pub fn jump_to_line(&mut self, line: usize) {
+        let from = self.current_line;
+        self.reveal_line(line);
+        let max_line = self.file_reader.line_count().saturating_sub(self.viewport_height);
+        self.set_top_line(line.min(max_line));
+        self.move_cursor_to((line, self.cursor.1));
+        self.remember_jump(from);
+    }
+    
+    /// Add the position before a move to the jump list if the move went off screen
+    fn remember_jump(&mut self, from: usize) {
+        if self.current_line.abs_diff(from) >= self.viewport_height.max(1) {
+            self.jump_list.record(from);
+        }
+    }
+    
+    pub fn jump_back(&mut self) {
+        match self.jump_list.back(self.current_line) {
+            Some(line) => self.scroll_to(line),
+            None => self.set_status_message("Already at the oldest jump"),
+        }
+    }
+    
+    pub fn jump_forward(&mut self) {
+        match self.jump_list.forward() {
+            Some(line) => self.scroll_to(line),
+            None => self.set_status_message("Already at the newest jump"),
+        }
+    }
+    
+    /// Move the viewport without recording a jump
+    fn scroll_to(&mut self, line: usize) {
+        let max_line = self.file_reader.line_count().saturating_sub(self.viewport_height);
+        self.set_top_line(line.min(max_line));
+        self.move_cursor_to((line, self.cursor.1));
+    }
+    
+    /// The line that line-oriented commands act on, which is the cursor's
+    fn focus_line(&self) -> usize {
+        self.cursor.0
+    }
+    
+    /// The last line with at least its first row in the viewport
+    fn last_line_in_viewport(&self) -> usize {
+        let line_count = self.file_reader.line_count();
+        let mut line_num = self.current_line;
+        let mut rows = self.row_count(line_num) - self.top_sub_row;
+        loop {
+            let next = self.next_visible_line(line_num);
+            if next >= line_count || rows >= self.viewport_height {
+                return line_num;
+            }
+            rows += self.row_count(next);
+            line_num = next;
+        }
+    }
+    
+    /// The top line that puts `line` on the bottom row of the viewport, or as close to it as
+    /// the start of the file allows
+    fn top_line_for_bottom(&self, line: usize) -> usize {
+        let mut top = self.shown_line(line);
+        let mut rows = self.row_count(top);
+        while let Some(previous) = self.prev_visible_line(top) {
+            rows += self.row_count(previous);
+            if rows > self.viewport_height {
+                break;
+            }
+            top = previous;
+        }
+        top
+    }
+    
+    // Text cursor
+    /// The cursor's column, pulled back onto the last character of its line
+    fn cursor_column(&self) -> usize {
+        let length = self.file_reader.get_line(self.cursor.0).map_or(0, TextUtils::char_len);
+        self.cursor.1.min(length.saturating_sub(1))
+    }
+    
+    /// Put the cursor on a line that is shown, without scrolling, and stretch the visual
+    /// selection to it
+    fn move_cursor_to(&mut self, (line, column): (usize, usize)) {
+        let line = self.shown_line(line.min(self.file_reader.line_count().saturating_sub(1)));
+        self.cursor = (line, column);
+        if let Some(mode) = self.visual {
+            self.selection = Some(Selection::visual(mode, self.visual_anchor, (line, self.cursor_column()), &self.file_reader));
+        }
+    }
+    
+    /// Scroll just far enough to bring the cursor's line into view
+    fn scroll_to_cursor(&mut self) {
+        let line = self.cursor.0;
+        if line < self.current_line || (line == self.current_line && self.top_sub_row > 0) {
+            self.set_top_line(line);
+        } else if line > self.last_line_in_viewport() {
+            self.set_top_line(self.top_line_for_bottom(line));
+        }
+    }
+    
+    /// Pull the cursor back into view after scrolling away from it
+    fn keep_cursor_in_viewport(&mut self) {
+        let line = self.cursor.0.clamp(self.current_line, self.last_line_in_viewport());
+        if line != self.cursor.0 || self.shown_line(line) != line {
+            self.move_cursor_to((line, self.cursor.1));
+        }
+    }
+    
+    /// Move the cursor by `delta` shown lines, stepping over closed folds
+    pub fn move_cursor_lines(&mut self, delta: isize) {
+        let last_line = self.file_reader.line_count().saturating_sub(1);
+        let mut line = self.cursor.0;
+        for _ in 0..delta.unsigned_abs() {
+            let next = if delta < 0 { self.prev_visible_line(line) } else { Some(self.next_visible_line(line)) };
+            match next {
+                Some(next) if next <= last_line => line = next,
+                _ => break,
+            }
+        }
+        self.move_cursor_to((line, self.cursor.1));
+        self.scroll_to_cursor();
+    }
+    
+    /// Move the cursor by `delta` characters within its line
+    pub fn move_cursor_columns(&mut self, delta: isize) {
+        let column = self.cursor_column().saturating_add_signed(delta);
+        self.move_cursor_to((self.cursor.0, column));
+        self.cursor.1 = self.cursor_column();
+        self.scroll_to_cursor();
+    }
+    
+    pub fn move_cursor_to_line_start(&mut self) {
+        self.move_cursor_to((self.cursor.0, 0));
+        self.scroll_to_cursor();
+    }
+    
+    /// Move to the last character, staying at the end of each line when moving up or down
+    pub fn move_cursor_to_line_end(&mut self) {
+        self.move_cursor_to((self.cursor.0, usize::MAX));
+        self.scroll_to_cursor();
+    }
+    
+    /// Start selecting from the cursor with `v` or `V`. The same key again stops, and the
+    /// other key switches modes while keeping the anchor.
+    pub fn toggle_visual_mode(&mut self, mode: VisualMode) {
+        match self.visual {
+            Some(current) if current == mode => self.clear_selection(),
+            Some(_) => {
+                self.visual = Some(mode);
+                self.move_cursor_to(self.cursor);
+            }
+            None => {
+                self.context_menu = None;
+                self.visual = Some(mode);
+                self.visual_anchor = (self.cursor.0, self.cursor_column());
+                self.move_cursor_to(self.cursor);
+            }
+        }
+    }
+    
+    pub fn is_in_visual_mode(&self) -> bool {
+        self.visual.is_some()
+    }
+    
+    pub fn clear_selection(&mut self) {
+        self.visual = None;
+        self.selection = None;
+    }
+    
+    /// Copy the selection with `y`, leaving the visual mode at the start of the selection
+    pub fn yank_selection(&mut self) {
+        let Some(ref selection) = self.selection else {
+            self.set_status_message("Nothing selected, v or V starts a selection");
+            return;
+        };
+        let (start_line, start_col, _, _) = selection.normalize();
+        self.copy_selection(false);
+        
+        if self.visual.is_some() {
+            self.clear_selection();
+            self.move_cursor_to((start_line, start_col));
+            self.scroll_to_cursor();
+        }
+    }
+    
+    // Record expansion
+    /// Pretty-print a JSON record in place of its line, or collapse it again. With a count,
+    /// the record on that line number is toggled and brought into view.
+    pub fn toggle_record_expansion(&mut self, line_number: Option<usize>) {
+        let line_num = match line_number {
+            Some(line_number) => {
+                let line_num = line_number.saturating_sub(1).min(self.file_reader.line_count().saturating_sub(1));
+                self.jump_to_line(line_num);
+                line_num
+            }
+            None => self.focus_line(),
+        };
+        
+        let Some(line) = self.file_reader.get_line(line_num) else {
+            return;
+        };
+        match self.expansions.toggle(line_num, line) {
+            Ok(true) => {}
+            Ok(false) => {
+                if self.current_line == line_num {
+                    self.top_sub_row = 0;
                 }
             }
+            Err(e) => self.set_status_message(format!("Line {} is not a JSON record: {:#}", line_num + 1, e)),
+        }
+    }
+    
+    pub fn collapse_all_records(&mut self) {
+        self.expansions.clear();
+        self.top_sub_row = 0;
+    }
+    
+    // Folding
+    /// Index the nesting of the shown file in the background, for folding and `%`
+    fn request_structure_index(&mut self) {
+        if let Some(task) = self.structure_task.take() {
+            task.cancel.store(true, Ordering::Relaxed);
+        }
+        self.structure = None;
+        self.folds.clear();
+        self.folds_before_filter.clear();
+        self.line_path = None;
+        
+        // JSON Lines records sit on one line each, so there is nothing to fold
+        let syntax = match (self.syntax, self.format_kind) {
+            (Some(syntax), Some(kind)) if kind != FormatKind::JsonLines => syntax,
+            _ => return,
+        };
+        
+        self.next_task_id += 1;
+        let task_id = self.next_task_id;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.structure_task = Some(BackgroundTask { id: task_id, cancel: cancel.clone() });
+        
+        let file_reader = self.file_reader.clone();
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            if let Some(index) = StructureIndex::build(&file_reader, syntax, &cancel) {
+                let _ = events.send(AppEvent::StructureIndexed { task_id, index });
+            }
+        });
+    }
+    
+    pub fn finish_structure_index(&mut self, task_id: u64, index: StructureIndex) {
+        if self.structure_task.as_ref().map(|task| task.id) == Some(task_id) {
+            self.structure_task = None;
+            self.structure = Some(Arc::new(index));
+            self.line_path = None;
+        }
+    }
+    
+    /// The structure index, or `None` with an explanation in the status bar
+    fn structure_or_explain(&mut self) -> Option<&StructureIndex> {
+        if self.structure.is_none() {
+            let message = if self.structure_task.is_some() {
+                "Still indexing the document structure"
+            } else {
+                "Folding needs a JSON or XML document"
+            };
+            self.set_status_message(message);
+        }
+        self.structure.as_deref()
+    }
+    
+    /// Keep the path shown in the status bar in step with the focus line
+    fn update_line_path(&mut self) {
+        let focus_line = self.focus_line();
+        if self.line_path.as_ref().is_some_and(|&(line, _)| line == focus_line) {
+            return;
+        }
+        
+        self.line_path = match (&self.structure, self.syntax) {
+            (Some(structure), Some(syntax)) if structure.root().is_some() => {
+                Some((focus_line, DocumentQuery::path_of_line(syntax, structure, &self.file_reader, focus_line)))
+            }
+            _ => None,
+        };
+    }
+    
+    /// The closed fold hiding a line, if any. The opening line of a fold stays visible.
+    fn closed_fold_at(&self, line_num: usize) -> Option<StructureBlock> {
+        self.folds
+            .range(..=line_num)
+            .next_back()
+            .map(|(_, &block)| block)
+            .filter(|block| block.end >= line_num)
+    }
+    
+    /// Open the fold hiding a line so it can be shown
+    fn reveal_line(&mut self, line_num: usize) {
+        if let Some(block) = self.closed_fold_at(line_num).filter(|block| block.start != line_num) {
+            self.folds.remove(&block.start);
+        }
+    }
+    
+    pub fn begin_fold_command(&mut self) {
+        self.pending_fold = true;
+    }
+    
+    pub fn has_pending_fold(&self) -> bool {
+        self.pending_fold
+    }
+    
+    /// Finish a `z` command: `za` toggles, `zo` opens and `zc` closes the fold at the focus
+    /// line, `zR` opens every fold and `{N}zM` folds every block N levels deep
+    pub fn complete_fold_command(&mut self, key: char, count: Option<usize>) {
+        self.pending_fold = false;
+        if self.is_filtered() {
+            self.set_status_message("Folding is off while filtering, esc clears the filter");
+            return;
+        }
+        match key {
+            'a' => self.toggle_fold(),
+            'o' => self.open_fold(),
+            'c' => self.close_fold(),
+            'R' => self.open_all_folds(),
+            'M' => self.fold_at_depth(count.unwrap_or(1)),
+            _ => {}
+        }
+    }
+    
+    pub fn cancel_fold_command(&mut self) {
+        self.pending_fold = false;
+    }
+    
+    fn toggle_fold(&mut self) {
+        if self.closed_fold_at(self.focus_line()).is_some() {
+            self.open_fold();
+        } else {
+            self.close_fold();
+        }
+    }
+    
+    fn open_fold(&mut self) {
+        if let Some(block) = self.closed_fold_at(self.focus_line()) {
+            self.folds.remove(&block.start);
+        }
+    }
+    
+    fn close_fold(&mut self) {
+        let focus_line = self.focus_line();
+        let Some(structure) = self.structure_or_explain() else {
+            return;
+        };
+        let Some(block) = structure.innermost_at(focus_line).map(|index| *structure.block(index)) else {
+            self.set_status_message("No block to fold here");
+            return;
+        };
+        
+        // Folds inside the new one are dropped so closed folds never overlap
+        self.folds.retain(|&start, _| start < block.start || start > block.end);
+        self.folds.insert(block.start, block);
+        self.set_top_line(self.current_line);
+    }
+    
+    fn open_all_folds(&mut self) {
+        self.folds.clear();
+    }
+    
+    /// Close every block at a nesting depth, where the outermost block is depth 0
+    fn fold_at_depth(&mut self, depth: usize) {
+        let Some(structure) = self.structure_or_explain() else {
+            return;
+        };
+        let folds: BTreeMap<usize, StructureBlock> = structure
+            .blocks_at_depth(depth)
+            .map(|index| *structure.block(index))
+            .map(|block| (block.start, block))
+            .collect();
+        
+        let message = format!("Folded {} blocks at depth {}", TextUtils::format_count(folds.len()), depth);
+        self.folds = folds;
+        self.set_top_line(self.current_line);
+        self.set_status_message(message);
+    }
+    
+    /// Bring the other end of the bracket or element on the focus line into view
+    pub fn jump_to_matching(&mut self) {
+        let focus_line = self.focus_line();
+        let Some(structure) = self.structure_or_explain() else {
+            return;
+        };
+        match structure.matching_line(focus_line) {
+            Some(line) => self.jump_to_line(line),
+            None => self.set_status_message(format!("No bracket or tag to match on line {}", focus_line + 1)),
+        }
+    }
+    
+    // Table view
+    /// Switch between drawing delimited lines as columns and as plain text
+    pub fn toggle_table_view(&mut self) {
+        if self.table.take().is_some() {
+            self.set_status_message("Text view");
+            return;
+        }
+        
+        // Files not detected as CSV or TSV are split on tabs if the header has any
+        let delimiter = self.format_kind.and_then(|kind| kind.delimiter()).unwrap_or_else(|| {
+            match self.file_reader.get_line(0) {
+                Some(header) if header.contains('\t') => '\t',
+                _ => ',',
+            }
+        });
+        self.table = Some(TableView::new(delimiter));
+        self.clear_selection();
+        self.set_status_message("Table view, left/right: scroll columns, x: hide column, X: show all, |: jump to column");
+    }
+    
+    pub fn is_table_view(&self) -> bool {
+        self.table.is_some()
+    }
+    
+    fn table_or_explain(&mut self) -> Option<&mut TableView> {
+        if self.table.is_none() {
+            self.set_status_message("Column commands work in the table view, press t");
+        }
+        self.table.as_mut()
+    }
+    
+    fn column_count(&self) -> usize {
+        self.table.as_ref().map_or(0, |table| table.header(&self.file_reader).len())
+    }
+    
+    pub fn scroll_columns(&mut self, delta: isize) {
+        let column_count = self.column_count();
+        if let Some(table) = self.table_or_explain() {
+            table.scroll_columns(delta, column_count);
+        }
+    }
+    
+    /// Hide a column given by its 1-based number, or the leftmost one shown
+    pub fn hide_column(&mut self, number: Option<usize>) {
+        let column_count = self.column_count();
+        let Some(table) = self.table_or_explain() else {
+            return;
+        };
+        let column = number.map_or(table.first_column(), |number| number.saturating_sub(1));
+        if !table.hide_column(column, column_count) {
+            self.set_status_message(format!("Can't hide column {} of {}", column + 1, column_count));
+        }
+    }
+    
+    pub fn show_all_columns(&mut self) {
+        if let Some(table) = self.table_or_explain() {
+            table.show_all_columns();
+        }
+    }
+    
+    /// Scroll the table to a column given by its 1-based number
+    pub fn jump_to_column(&mut self, number: usize) {
+        self.jump_to_named_column(&number.to_string());
+    }
+    
+    fn jump_to_named_column(&mut self, input: &str) {
+        let Some(table) = self.table.as_ref() else {
+            return;
+        };
+        match TableView::find_column(&table.header(&self.file_reader), input) {
+            Some(column) => {
+                if let Some(table) = self.table.as_mut() {
+                    table.scroll_to_column(column);
+                }
+            }
+            None => self.set_status_message(format!("No column matches '{}'", input.trim())),
+        }
+    }
+    
+    // Marks
+    pub fn begin_mark(&mut self, action: MarkAction) {
+        self.pending_mark = Some(action);
+    }
+    
+    pub fn has_pending_mark(&self) -> bool {
+        self.pending_mark.is_some()
+    }
+    
+    /// Finish a pending `m` or `'` command with the given mark name
+    pub fn complete_mark(&mut self, name: char) {
+        let Some(action) = self.pending_mark.take() else {
+            return;
+        };
+        
+        if !Marks::is_valid_name(name) {
+            self.set_status_message(format!("Invalid mark name '{}', use a-z", name));
+            return;
+        }
+        
+        match action {
+            MarkAction::Set => {
+                self.marks.set(name, self.focus_line());
+                self.set_status_message(format!("Mark '{}' set", name));
+            }
+            MarkAction::Jump => match self.marks.get(name) {
+                Some(line) => self.jump_to_line(line),
+                None => self.set_status_message(format!("Mark '{}' is not set", name)),
+            },
+        }
+    }
+    
+    pub fn cancel_mark(&mut self) {
+        self.pending_mark = None;
+    }
+    
+    // Numeric prefixes such as the `123` in `123g`
+    pub fn has_pending_count(&self) -> bool {
+        self.pending_count.is_some()
+    }
+    
+    pub fn push_count_digit(&mut self, digit: u32) {
+        let count = self.pending_count.unwrap_or(0);
+        self.pending_count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+    }
+    
+    pub fn take_pending_count(&mut self) -> Option<usize> {
+        self.pending_count.take()
+    }
+    
+    pub fn goto_start(&mut self) {
+        let from = self.current_line;
+        self.set_top_line(0);
+        self.move_cursor_to((0, self.cursor.1));
+        self.remember_jump(from);
+    }
+    
+    pub fn goto_end(&mut self) {
+        let from = self.current_line;
+        
+        // Walk back from the last line until the viewport is full, so that the end of the
+        // file appears at the bottom however many rows folds and expansions take up
+        let last_line = self.file_reader.line_count().saturating_sub(1);
+        self.set_top_line(self.top_line_for_bottom(last_line));
+        self.move_cursor_to((last_line, self.cursor.1));
+        self.remember_jump(from);
+    }
+    
+    // Selection operations
+    /// Start selecting at a click. A double-click selects the word under the mouse and a
+    /// triple-click the whole line.
+    pub fn start_selection(&mut self, col: u16, row: u16) {
+        self.context_menu = None;
+        
+        if let Some((line, column)) = self.screen_to_text_coords(col, row) {
+            let clicks = self.count_clicks(col, row);
+            let text = self.file_reader.get_line(line).unwrap_or("");
+            let selection = match clicks {
+                2 => TextUtils::word_at(text, column, &self.word_chars).map(|(start, end)| Selection {
+                    start_line: line,
+                    start_col: start,
+                    end_line: line,
+                    end_col: end,
+                }),
+                3 => Some(Selection { start_line: line, start_col: 0, end_line: line, end_col: TextUtils::char_len(text) }),
+                _ => None,
+            };
+            
+            self.visual = None;
+            self.cursor = (line, column);
+            self.selection = Some(selection.unwrap_or_else(|| Selection::new(line, column)));
+            self.selecting = true;
+        }
+    }
+    
+    /// Count a click as the next of a double or triple click if it lands on the same cell
+    /// soon after the last one
+    fn count_clicks(&mut self, col: u16, row: u16) -> u8 {
+        let now = Instant::now();
+        let interval = Duration::from_millis(Constants::MULTI_CLICK_INTERVAL_MS);
+        let clicks = match self.last_click {
+            Some((time, position, clicks)) if position == (col, row) && now.duration_since(time) <= interval => clicks % 3 + 1,
+            _ => 1,
+        };
+        self.last_click = Some((now, (col, row), clicks));
+        clicks
+    }
+    
+    /// Stretch the selection being dragged to the mouse. Past the top or bottom of the view,
+    /// the view keeps scrolling until the mouse comes back or the button is released.
+    pub fn update_selection(&mut self, col: u16, row: u16) {
+        if !self.selecting {
+            return;
+        }
+        self.drag_position = (col, row);
+        
+        if self.drag_direction().is_some() {
+            self.start_drag_scroll();
+        } else if let Some(task) = self.drag_scroll_task.take() {
+            task.cancel.store(true, Ordering::Relaxed);
+        }
+        
+        if let Some((line, column)) = self.drag_coords(col, row) {
+            if let Some(ref mut selection) = self.selection {
+                selection.update_end(line, column);
+            }
+        }
+    }
+    
+    /// Shift-click: move the end of the selection to the mouse, or select from the cursor if
+    /// there is nothing selected yet
+    pub fn extend_selection(&mut self, col: u16, row: u16) {
+        self.context_menu = None;
+        let Some((line, column)) = self.screen_to_text_coords(col, row) else {
+            return;
+        };
+        
+        if self.visual.is_some() {
+            self.move_cursor_to((line, column));
+            return;
+        }
+        let (cursor_line, cursor_column) = (self.cursor.0, self.cursor_column());
+        self.selection
+            .get_or_insert_with(|| Selection::new(cursor_line, cursor_column))
+            .update_end(line, column);
+        self.selecting = true;
+    }
+    
+    /// -1 while dragging above the text rows, 1 while dragging below them
+    fn drag_direction(&self) -> Option<isize> {
+        let row = self.drag_position.1 as usize;
+        if row == 0 {
+            Some(-1)
+        } else if row > self.viewport_height {
+            Some(1)
+        } else {
+            None
+        }
+    }
+    
+    fn start_drag_scroll(&mut self) {
+        if self.drag_scroll_task.is_some() {
+            return;
+        }
+        
+        self.next_task_id += 1;
+        let task_id = self.next_task_id;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.drag_scroll_task = Some(BackgroundTask { id: task_id, cancel: cancel.clone() });
+        
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            let interval = Duration::from_millis(Constants::DRAG_SCROLL_INTERVAL_MS);
+            loop {
+                std::thread::sleep(interval);
+                if cancel.load(Ordering::Relaxed) || events.send(AppEvent::DragScroll { task_id }).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    
+    /// Scroll one line towards the mouse and take the end of the selection to the edge of the view
+    pub fn drag_scroll(&mut self, task_id: u64) {
+        if self.drag_scroll_task.as_ref().map(|task| task.id) != Some(task_id) {
+            return;
+        }
+        let Some(direction) = self.drag_direction() else {
+            return;
+        };
+        
+        // The rows from the last frame are out of date once scrolled, so the edge line is worked out here
+        let line = if direction < 0 {
+            self.scroll_up();
+            self.current_line
+        } else {
+            self.scroll_down();
+            self.last_line_in_viewport()
+        };
+        let column = (self.drag_position.0 as usize).saturating_sub(Constants::LINE_NUMBER_WIDTH as usize + 1);
+        if let Some(ref mut selection) = self.selection {
+            selection.update_end(line, column);
         }
     }
     
     pub fn end_selection(&mut self) {
         self.selecting = false;
+        if let Some(task) = self.drag_scroll_task.take() {
+            task.cancel.store(true, Ordering::Relaxed);
+        }
         
         if let Some(ref selection) = self.selection {
             if selection.is_empty() {
@@ -467,99 +1684,661 @@ impl Viewer {
         self.context_menu = None;
     }
     
+    /// Open the context menu below the cursor, for choosing an action from the keyboard
+    pub fn open_context_menu_at_cursor(&mut self) {
+        if self.selection.is_none() {
+            self.set_status_message("Nothing selected, v or V starts a selection");
+            return;
+        }
+        let row = self.visible_rows.iter().position(|row| row.text_line() == Some(self.cursor.0)).unwrap_or(0);
+        let x = 1 + Constants::LINE_NUMBER_WIDTH + self.cursor_column() as u16;
+        self.context_menu = Some(ContextMenu::new(x, row as u16 + 2));
+    }
+    
     pub fn is_mouse_in_menu(&self, col: u16, row: u16) -> bool {
-        if let Some(ref menu) = self.context_menu {
-            let menu_height = menu.items.len() as u16;
-            col >= menu.x && col < menu.x + Constants::CONTEXT_MENU_WIDTH &&
-            row >= menu.y && row < menu.y + menu_height
-        } else {
-            false
+        self.context_menu.as_ref().is_some_and(|menu| menu.item_at(self.screen, col, row).is_some())
+    }
+    
+    pub fn handle_menu_click(&mut self, col: u16, row: u16) {
+        let action = self.context_menu.as_ref().and_then(|menu| menu.item_at(self.screen, col, row).map(|index| menu.items[index]));
+        self.context_menu = None;
+        if let Some(action) = action {
+            self.run_menu_action(action);
         }
     }
     
-    pub fn handle_menu_click(&mut self, _col: u16, row: u16) {
-        if let Some(ref menu) = self.context_menu {
-            let item_index = (row - menu.y) as usize;
-            if item_index < menu.items.len() {
-                match item_index {
-                    0 => self.copy_selection(),
-                    1 => self.search_selection(),
-                    _ => {}
-                }
-            }
+    pub fn move_menu_selection(&mut self, delta: isize) {
+        if let Some(ref mut menu) = self.context_menu {
+            menu.move_selection(delta);
+        }
+    }
+    
+    pub fn run_selected_menu_action(&mut self) {
+        let action = self.context_menu.take().and_then(|menu| menu.selected_action());
+        if let Some(action) = action {
+            self.run_menu_action(action);
+        }
+    }
+    
+    /// Act on the selection. The selection is kept, but keys no longer extend it.
+    fn run_menu_action(&mut self, action: MenuAction) {
+        self.visual = None;
+        match action {
+            MenuAction::Copy => self.copy_selection(false),
+            MenuAction::CopyWithLineNumbers => self.copy_selection(true),
+            MenuAction::Search => self.search_selection(),
+            MenuAction::Filter => self.filter_selection(),
+            MenuAction::Highlight => self.highlight_selection(),
+            MenuAction::SetBookmark => self.bookmark_selection(),
+            MenuAction::SaveToFile => self.enter_selection_prompt(PromptKind::Save),
+            MenuAction::PipeToCommand => self.enter_selection_prompt(PromptKind::Pipe),
         }
-        self.context_menu = None;
     }
     
     // Utility methods
     fn screen_to_text_coords(&self, col: u16, row: u16) -> Option<(usize, usize)> {
-        if row == 0 || col < Constants::LINE_NUMBER_WIDTH {
+        // Table cells are padded and truncated, so screen columns don't map back to the text
+        if row == 0 || col <= Constants::LINE_NUMBER_WIDTH || self.table.is_some() {
             return None;
         }
         
+        // The border takes one column before the gutter
         let text_row = (row - 1) as usize;
-        let text_col = (col - Constants::LINE_NUMBER_WIDTH) as usize;
+        let text_col = (col - Constants::LINE_NUMBER_WIDTH - 1) as usize;
         
-        if text_row >= self.viewport_height {
-            return None;
+        // Rows of expanded records are not part of the file's text
+        let line_num = self.visible_rows.get(text_row)?.text_line()?;
+        Some((line_num, text_col))
+    }
+    
+    /// Like `screen_to_text_coords`, but a point outside the text is pulled onto its nearest
+    /// edge, so that a drag can leave the text without losing the selection
+    fn drag_coords(&self, col: u16, row: u16) -> Option<(usize, usize)> {
+        let last_row = self.visible_rows.len().min(self.viewport_height).max(1) as u16;
+        self.screen_to_text_coords(col.max(Constants::LINE_NUMBER_WIDTH + 1), row.clamp(1, last_row))
+    }
+    
+    fn copy_selection(&mut self, line_numbers: bool) {
+        let Some(ref selection) = self.selection else {
+            return;
+        };
+        
+        // A selection too big for the clipboard is turned down before its text is put together
+        let (start_line, _, end_line, _) = selection.normalize();
+        let mut bytes = selection.byte_len(&self.file_reader);
+        if line_numbers {
+            bytes += (end_line - start_line + 1) * ((end_line + 1).to_string().len() + 2);
+        }
+        if let Err(e) = self.clipboard.check_size(bytes) {
+            self.set_status_message(format!("Copy failed: {:#}", e));
+            return;
         }
         
-        let line_num = self.current_line + text_row;
-        if line_num >= self.file_reader.line_count() {
+        let text = if line_numbers {
+            selection.get_numbered_text(&self.file_reader)
+        } else {
+            selection.get_text(&self.file_reader)
+        };
+        let message = match text.map(|text| self.clipboard.copy(&text)) {
+            Some(Ok(())) => format!("Copied {} to the {}", Self::describe_lines(selection), self.clipboard.description()),
+            Some(Err(e)) => format!("Copy failed: {:#}", e),
+            None => "Copy failed: the selection is empty".to_string(),
+        };
+        self.set_status_message(message);
+    }
+    
+    /// "1 line" or "12 lines", for the lines a selection spans
+    fn describe_lines(selection: &Selection) -> String {
+        let (start_line, _, end_line, _) = selection.normalize();
+        let lines = end_line - start_line + 1;
+        format!("{} line{}", TextUtils::format_count(lines), if lines == 1 { "" } else { "s" })
+    }
+    
+    /// The selected text, if it is on one line and so can be searched for
+    fn selection_search_term(&mut self) -> Option<String> {
+        let text = self.selection.as_ref()?.get_text(&self.file_reader)?;
+        if text.contains('\n') {
+            self.set_status_message("Only text within one line can be searched for");
             return None;
         }
+        Some(text)
+    }
+    
+    fn search_selection(&mut self) {
+        if let Some(text) = self.selection_search_term() {
+            self.search_textarea.delete_line_by_head();
+            self.search_textarea.delete_line_by_end();
+            self.search_textarea.insert_str(&text);
+            self.request_search();
+        }
+    }
+    
+    /// Search for the selected text and show only the lines with matches, until the search
+    /// is cleared and the folds closed before come back
+    fn filter_selection(&mut self) {
+        if self.selection_search_term().is_some() {
+            if !self.filtering {
+                self.folds_before_filter = std::mem::take(&mut self.folds);
+            }
+            self.filtering = true;
+            self.search_selection();
+        }
+    }
+    
+    /// Highlight the selected text everywhere, or stop if it is highlighted already
+    fn highlight_selection(&mut self) {
+        let Some(text) = self.selection_search_term() else {
+            return;
+        };
+        match self.highlights.iter().position(|highlight| *highlight == text) {
+            Some(index) => {
+                self.highlights.remove(index);
+                self.set_status_message(format!("Stopped highlighting '{}'", text));
+            }
+            None => {
+                self.set_status_message(format!("Highlighting '{}', esc: clear", text));
+                self.highlights.push(text);
+            }
+        }
+        // Highlights are laid out along with the search matches
+        self.search_generation += 1;
+    }
+    
+    /// Put the first free mark on the first line of the selection
+    fn bookmark_selection(&mut self) {
+        let Some((line, _, _, _)) = self.selection.as_ref().map(Selection::normalize) else {
+            return;
+        };
+        match self.marks.first_free() {
+            Some(name) => {
+                self.marks.set(name, line);
+                self.set_status_message(format!("Mark '{}' set on line {}, '{}: jump to it", name, line + 1, name));
+            }
+            None => self.set_status_message("Every mark from a to z is in use, m{a-z} moves one"),
+        }
+    }
+    
+    fn enter_selection_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(kind);
+        self.prompt_textarea.delete_line_by_head();
+        self.prompt_textarea.delete_line_by_end();
+    }
+    
+    /// Write the selection to a new file. An existing file is left alone.
+    fn save_selection(&mut self, path: &str) {
+        let Some(ref selection) = self.selection else {
+            return;
+        };
+        if path.is_empty() {
+            return;
+        }
+        if selection.byte_len(&self.file_reader) == 0 {
+            self.set_status_message("Nothing to save, the selection is empty");
+            return;
+        }
         
-        Some((line_num, text_col))
+        // Written a line at a time, so saving a huge selection doesn't copy it into memory
+        let result = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                selection.write_text(&self.file_reader, &mut writer)?;
+                writer.write_all(b"\n")?;
+                writer.flush()
+            });
+        let message = match result {
+            Ok(()) => format!("Saved {} to {}", Self::describe_lines(selection), path),
+            Err(e) => format!("Failed to save to {}: {}", path, e),
+        };
+        self.set_status_message(message);
     }
     
-    fn copy_selection(&mut self) {
-        if let Some(ref selection) = self.selection {
-            let _ = selection.copy_to_clipboard(&self.file_reader);
+    /// Run a shell command with the selection as its input, on a background thread
+    fn pipe_selection(&mut self, command: &str) {
+        if command.is_empty() {
+            return;
         }
+        let Some(selection) = self.selection.clone().filter(|selection| selection.byte_len(&self.file_reader) > 0) else {
+            self.set_status_message("Nothing to pipe, the selection is empty");
+            return;
+        };
+        
+        self.next_task_id += 1;
+        let task_id = self.next_task_id;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.command_task = Some(BackgroundTask { id: task_id, cancel: cancel.clone() });
+        
+        self.set_status_message(format!("Running '{}'..., esc: cancel", command));
+        let command = command.to_string();
+        let file_reader = self.file_reader.clone();
+        let output_path = std::env::temp_dir().join(format!("bigview_output_{}_{}", std::process::id(), task_id));
+        let events = self.events.clone();
+        std::thread::spawn(move || {
+            let result = Self::run_command(&command, selection, file_reader, &output_path, &cancel);
+            if result.is_err() {
+                let _ = std::fs::remove_file(&output_path);
+            }
+            let _ = events.send(AppEvent::CommandFinished { task_id, command, result });
+        });
     }
     
-    fn search_selection(&mut self) {
-        if let Some(ref selection) = self.selection {
-            if let Some(text) = selection.get_text(&self.file_reader) {
-                if !text.contains('\n') {
-                    self.search_textarea.delete_line_by_head();
-                    self.search_textarea.delete_line_by_end();
-                    self.search_textarea.insert_str(&text);
-                    self.request_search();
+    /// Run a command with its output going to `output_path`, killing it if it is cancelled
+    /// or runs out of time
+    fn run_command(command: &str, selection: Selection, file_reader: FileReader, output_path: &Path, cancel: &AtomicBool) -> anyhow::Result<CommandOutput> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut child = std::process::Command::new(shell)
+            .args([flag, command])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::fs::File::create(output_path)?)
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        
+        // Input is written from its own thread, so a command that writes before it has
+        // read everything can't block on a full pipe
+        if let Some(stdin) = child.stdin.take() {
+            std::thread::spawn(move || {
+                let mut writer = BufWriter::new(stdin);
+                let _ = selection
+                    .write_text(&file_reader, &mut writer)
+                    .and_then(|_| writer.write_all(b"\n"))
+                    .and_then(|_| writer.flush());
+            });
+        }
+        let stderr_reader = child.stderr.take().map(|mut stderr| {
+            std::thread::spawn(move || {
+                let mut text = Vec::new();
+                let _ = stderr.read_to_end(&mut text);
+                text
+            })
+        });
+        
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            let timed_out = started.elapsed() >= Duration::from_secs(Constants::PIPE_COMMAND_TIMEOUT_SECS);
+            if timed_out || cancel.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                let _ = child.wait();
+                if timed_out {
+                    anyhow::bail!("killed after {} seconds", Constants::PIPE_COMMAND_TIMEOUT_SECS);
                 }
+                anyhow::bail!("cancelled");
             }
+            std::thread::sleep(Duration::from_millis(Constants::PIPE_COMMAND_POLL_MS));
+        };
+        
+        let stderr = stderr_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        Ok(CommandOutput {
+            status,
+            path: output_path.to_path_buf(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        })
+    }
+    
+    pub fn is_command_running(&self) -> bool {
+        self.command_task.is_some()
+    }
+    
+    /// Kill the piped command. Whatever its thread sends back is ignored.
+    pub fn cancel_command(&mut self) {
+        if let Some(task) = self.command_task.take() {
+            task.cancel.store(true, Ordering::Relaxed);
+            self.set_status_message("Command cancelled");
         }
     }
     
+    /// Show a piped command's output: one line in the status bar, more in a viewer of its own
+    pub fn finish_command(&mut self, task_id: u64, command: &str, result: anyhow::Result<CommandOutput>) -> ViewerAction {
+        if self.command_task.as_ref().map(|task| task.id) != Some(task_id) {
+            if let Ok(output) = result {
+                let _ = std::fs::remove_file(&output.path);
+            }
+            return ViewerAction::None;
+        }
+        self.command_task = None;
+        
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                self.set_status_message(format!("Failed to run '{}': {:#}", command, e));
+                return ViewerAction::None;
+            }
+        };
+        let file_reader = match output.status.success() {
+            true => FileReader::new_with_progress(&output.path, None),
+            false => Err(anyhow::anyhow!("{}", output.status)),
+        };
+        let message = match file_reader {
+            // A trailing line break doesn't make a second line
+            Ok(file_reader) if file_reader.get_line(1).is_some() => {
+                return ViewerAction::ShowOutput { command: command.to_string(), file_reader };
+            }
+            Ok(file_reader) => match file_reader.get_line(0).filter(|line| !line.is_empty()) {
+                Some(line) => format!("{}: {}", command, line),
+                None => format!("'{}' finished without output", command),
+            },
+            Err(e) => match output.stderr.lines().next() {
+                Some(error) => format!("'{}' failed, {:#}: {}", command, e, error),
+                None => format!("'{}' failed, {:#}", command, e),
+            },
+        };
+        let _ = std::fs::remove_file(&output.path);
+        self.set_status_message(message);
+        ViewerAction::None
+    }
+    
+    /// A viewer over a piped command's output, which `q` leaves to come back here
+    pub fn open_output(&self, command: &str, file_reader: FileReader) -> Viewer {
+        let mut output = Viewer::new(file_reader, self.events.clone());
+        output.clipboard = self.clipboard;
+        output.word_chars = self.word_chars.clone();
+        // Task ids carry on, so every task in the stack of viewers has its own and its events
+        // can be handed to the viewer that started it
+        output.next_task_id = self.next_task_id;
+        output.set_status_message(format!("Output of '{}', q: back", command));
+        output
+    }
+    
+    /// Come back from a viewer made by `open_output`, deleting the output file behind it
+    pub fn close_output(&mut self, output: Viewer) {
+        self.next_task_id = self.next_task_id.max(output.next_task_id);
+        let _ = std::fs::remove_file(output.file_reader.path());
+        self.clear_status_message();
+        // Changes to the file while the output was shown went unnoticed
+        self.reload_file();
+    }
+    
     // Drawing methods
-    fn draw_content(&self, f: &mut Frame, area: Rect) {
-        let lines = self.file_reader.get_lines(self.current_line, self.viewport_height);
-        
-        let items: Vec<ListItem> = lines
-            .iter()
-            .enumerate()
-            .map(|(i, line)| {
-                let line_num = self.current_line + i;
-                let line_number = format!("{:6} ", line_num + 1);
-                
-                let mut spans = vec![Span::styled(line_number, Style::default().fg(Constants::LINE_NUMBER_COLOR))];
-                let text_spans = self.create_line_spans(line, line_num);
-                spans.extend(text_spans);
-                
-                ListItem::new(Line::from(spans))
-            })
-            .collect();
-
-        let list = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("File Viewer"));
-
-        f.render_widget(list, area);
+    fn draw_content(&mut self, f: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title("File Viewer");
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        
+        if let Some(table) = self.table.clone() {
+            self.draw_table(f, inner, &table);
+            return;
+        }
+        
+        // Layouts only stay valid while the highlighted ranges are unchanged
+        let layout_key = self.layout_key();
+        if self.layout_key != Some(layout_key) {
+            self.line_layouts.clear();
+            self.layout_key = Some(layout_key);
+        }
+        
+        let visible_rows = self.collect_visible_rows(inner.height as usize);
+        let right_edge = inner.x + inner.width;
+        
+        // Syntax state flows from one line to the next, starting afresh at the top of the
+        // viewport. A construct opened above the viewport is picked up from its end onwards.
+        let mut syntax_state = LineState::Normal;
+        let mut cursor_position = None;
+        
+        for (row, &display_row) in visible_rows.iter().enumerate() {
+            let y = inner.y + row as u16;
+            
+            match display_row {
+                DisplayRow::Line(line_num) | DisplayRow::Fold(StructureBlock { start: line_num, .. }) => {
+                    let x = self.draw_gutter(f, inner.x, y, right_edge, Some(line_num));
+                    let Some(line) = self.file_reader.get_line(line_num) else {
+                        continue;
+                    };
+                    
+                    // A layout cached before the start of a comment or tag above came into view
+                    // was coloured from the wrong state
+                    let cached = self.line_layouts.get(&line_num).is_some_and(|layout| layout.start_state == syntax_state);
+                    if !cached {
+                        let (ranges, end_state) = self.line_ranges(line, line_num, syntax_state);
+                        let segments = TextUtils::layout_segments(line, &ranges);
+                        self.line_layouts.insert(line_num, LineLayout { segments, start_state: syntax_state, end_state });
+                    }
+                    
+                    if line_num == self.cursor.0 {
+                        // Measured the way the line is drawn, where wide characters take two
+                        // cells and tabs and other control characters none
+                        let column = self.cursor_column();
+                        let byte = line.char_indices().nth(column).map_or(line.len(), |(byte, _)| byte);
+                        cursor_position = Some((x.saturating_add(Span::raw(&line[..byte]).width() as u16), y));
+                    }
+                    
+                    let layout = &self.line_layouts[&line_num];
+                    syntax_state = layout.end_state;
+                    let x = Self::draw_segments(f, x, y, right_edge, line, &layout.segments);
+                    
+                    if let DisplayRow::Fold(block) = display_row {
+                        // Whatever was open inside the fold is closed again by its last line
+                        syntax_state = LineState::Normal;
+                        self.draw_fold_summary(f, x, y, right_edge, &block);
+                    }
+                }
+                DisplayRow::Expansion { line, row } => {
+                    // Only the first row of a record carries the line number
+                    let x = self.draw_gutter(f, inner.x, y, right_edge, (row == 0).then_some(line));
+                    if let Some(text) = self.expansions.rows(line).and_then(|rows| rows.get(row)) {
+                        let mut ranges = Vec::new();
+                        SyntaxHighlighter::highlight(SyntaxKind::Json, text, LineState::Normal, &mut ranges);
+                        Self::draw_segments(f, x, y, right_edge, text, &TextUtils::layout_segments(text, &ranges));
+                    }
+                }
+            }
+        }
+        
+        // The terminal's own cursor marks the text cursor, unless a prompt or menu has the keys
+        if self.prompt.is_none() && self.context_menu.is_none() {
+            if let Some((x, y)) = cursor_position.filter(|&(x, _)| x < right_edge) {
+                f.set_cursor(x, y);
+            }
+        }
+        
+        // Only keep layouts for lines that are still on screen
+        self.line_layouts.retain(|&line_num, _| visible_rows.iter().any(|row| row.text_line() == Some(line_num)));
+        self.visible_rows = visible_rows;
+    }
+    
+    /// The rows shown from the top of the viewport, reusing the buffer from the last frame
+    fn collect_visible_rows(&mut self, height: usize) -> Vec<DisplayRow> {
+        let mut rows = std::mem::take(&mut self.visible_rows);
+        rows.clear();
+        
+        let line_count = self.file_reader.line_count();
+        let mut line_num = self.current_line;
+        let mut first_row = self.top_sub_row;
+        while rows.len() < height && line_num < line_count {
+            if let Some(&block) = self.folds.get(&line_num) {
+                rows.push(DisplayRow::Fold(block));
+            } else if let Some(expansion) = self.expansions.rows(line_num) {
+                let remaining = height - rows.len();
+                rows.extend((first_row..expansion.len()).take(remaining).map(|row| DisplayRow::Expansion { line: line_num, row }));
+            } else {
+                rows.push(DisplayRow::Line(line_num));
+            }
+            line_num = self.next_visible_line(line_num);
+            first_row = 0;
+        }
+        rows
+    }
+    
+    /// Returns the column after the last one drawn
+    fn draw_segments(f: &mut Frame, mut x: u16, y: u16, right_edge: u16, line: &str, segments: &Segments) -> u16 {
+        for &(start, end, style) in segments {
+            if x >= right_edge {
+                break;
+            }
+            let remaining = (right_edge - x) as usize;
+            x = f.buffer_mut().set_stringn(x, y, &line[start..end], remaining, style).0;
+        }
+        x
+    }
+    
+    /// Draw the header line on the first row and the lines from the top of the viewport below
+    /// it, split into cells and padded to the widths of the rows around the viewport
+    fn draw_table(&mut self, f: &mut Frame, inner: Rect, table: &TableView) {
+        let height = inner.height as usize;
+        let right_edge = inner.x + inner.width;
+        let sample_start = self.current_line.saturating_sub(height);
+        let widths = table.column_widths(&self.file_reader, sample_start..self.current_line + 2 * height);
+        let columns: Vec<usize> = table.visible_columns(widths.len()).collect();
+        
+        if height > 0 {
+            let x = self.draw_gutter(f, inner.x, inner.y, right_edge, None);
+            let header = table.header(&self.file_reader);
+            let cells = header.into_iter().map(|name| (name, Style::default().fg(Constants::TABLE_HEADER_COLOR).add_modifier(Modifier::BOLD)));
+            Self::draw_cells(f, x, inner.y, right_edge, &widths, &columns, cells.collect());
+        }
+        
+        // The header is already on screen, so it is skipped when the top of the file is reached
+        let mut visible_rows = self.collect_visible_rows(height + 1);
+        visible_rows.retain(|row| row.text_line() != Some(0));
+        visible_rows.truncate(height.saturating_sub(1));
+        
+        for (row, &display_row) in visible_rows.iter().enumerate() {
+            let y = inner.y + 1 + row as u16;
+            let Some(line_num) = display_row.text_line() else {
+                continue;
+            };
+            let x = self.draw_gutter(f, inner.x, y, right_edge, Some(line_num));
+            let Some(line) = self.file_reader.get_line(line_num) else {
+                continue;
+            };
+            
+            // A cell takes the style of the search match inside it, the current match first
+            let matches = self.matches_on_line(line_num);
+            let cells = table.parse_row_spans(line).into_iter().map(|(field, span)| {
+                let overlapping: Vec<usize> = matches
+                    .clone()
+                    .filter(|&index| self.search_matches[index].start < span.end && self.search_matches[index].end > span.start)
+                    .collect();
+                let style = if overlapping.contains(&self.current_match) {
+                    Style::default().bg(Constants::CURRENT_MATCH_BG_COLOR).fg(Constants::CURRENT_MATCH_FG_COLOR)
+                } else if !overlapping.is_empty() {
+                    Style::default().bg(Constants::OTHER_MATCH_BG_COLOR).fg(Constants::OTHER_MATCH_FG_COLOR)
+                } else {
+                    Style::default()
+                };
+                (field, style)
+            });
+            Self::draw_cells(f, x, y, right_edge, &widths, &columns, cells.collect());
+        }
+        
+        self.visible_rows = visible_rows;
+    }
+    
+    /// Draw the given columns of a row, each fitted to its width and separated by a bar
+    fn draw_cells(f: &mut Frame, mut x: u16, y: u16, right_edge: u16, widths: &[usize], columns: &[usize], cells: Vec<(String, Style)>) {
+        let separator_style = Style::default().fg(Constants::TABLE_SEPARATOR_COLOR);
+        for (position, &column) in columns.iter().enumerate() {
+            if x >= right_edge {
+                break;
+            }
+            if position > 0 {
+                x = f.buffer_mut().set_stringn(x, y, " │ ", (right_edge - x) as usize, separator_style).0;
+            }
+            
+            let width = widths[column];
+            let (text, style) = cells.get(column).map_or(("", Style::default()), |(text, style)| (text.as_str(), *style));
+            let mut cell: String = text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+            if TextUtils::char_len(&cell) > width {
+                cell = cell.chars().take(width.saturating_sub(1)).chain(std::iter::once('…')).collect();
+            }
+            let cell = format!("{:<width$}", cell, width = width);
+            x = f.buffer_mut().set_stringn(x, y, cell, right_edge.saturating_sub(x) as usize, style).0;
+        }
+    }
+    
+    /// The placeholder after a closed fold's first line, such as `…} 1,204 lines`
+    fn draw_fold_summary(&self, f: &mut Frame, x: u16, y: u16, right_edge: u16, block: &StructureBlock) {
+        let closing = match block.kind {
+            BlockKind::Object => "}",
+            BlockKind::Array => "]",
+            BlockKind::Element => self.file_reader.get_line(block.end).map_or("", str::trim),
+        };
+        let summary = format!("…{} {} lines", closing, TextUtils::format_count(block.end - block.start + 1));
+        f.buffer_mut().set_stringn(
+            x,
+            y,
+            summary,
+            right_edge.saturating_sub(x) as usize,
+            Style::default().fg(Constants::FOLD_SUMMARY_COLOR),
+        );
+    }
+    
+    /// Draw the line number and mark column, or a blank gutter for continuation rows.
+    /// Returns the column the text starts at.
+    fn draw_gutter(&mut self, f: &mut Frame, x: u16, y: u16, right_edge: u16, line_num: Option<usize>) -> u16 {
+        // The last gutter column shows the mark placed on this line, if any
+        self.gutter_buf.clear();
+        let mark = match line_num {
+            Some(line_num) => {
+                let _ = write!(self.gutter_buf, "{:6}", line_num + 1);
+                self.marks.mark_at(line_num).unwrap_or(' ')
+            }
+            None => {
+                self.gutter_buf.push_str("      ");
+                ' '
+            }
+        };
+        
+        let (x, _) = f.buffer_mut().set_stringn(
+            x,
+            y,
+            &self.gutter_buf,
+            right_edge.saturating_sub(x) as usize,
+            Style::default().fg(Constants::LINE_NUMBER_COLOR),
+        );
+        let (x, _) = f.buffer_mut().set_stringn(
+            x,
+            y,
+            mark.encode_utf8(&mut [0; 4]),
+            right_edge.saturating_sub(x) as usize,
+            Style::default().fg(Constants::MARK_COLOR),
+        );
+        x
     }
     
-    fn create_line_spans<'a>(&self, line: &'a str, line_num: usize) -> Vec<Span<'a>> {
+    fn layout_key(&self) -> LayoutKey {
+        LayoutKey {
+            selection: self.selection.as_ref().map(|selection| selection.normalize()),
+            search_generation: self.search_generation,
+            current_match: self.current_match,
+        }
+    }
+    
+    /// Styled character ranges for a line, ordered from lowest to highest priority.
+    /// Also returns the syntax state the next line starts in.
+    fn line_ranges(&self, line: &str, line_num: usize, syntax_state: LineState) -> (Vec<(usize, usize, Style)>, LineState) {
         let mut ranges = Vec::new();
         
-        // Add selection highlighting
+        // Syntax colouring goes underneath search and selection highlighting
+        let end_state = match self.syntax {
+            Some(syntax) => SyntaxHighlighter::highlight(syntax, line, syntax_state, &mut ranges),
+            None => LineState::Normal,
+        };
+        
+        // Highlighted text goes underneath the search matches
+        let highlight_style = Style::default().bg(Constants::HIGHLIGHT_BG_COLOR).fg(Constants::HIGHLIGHT_FG_COLOR);
+        for highlight in &self.highlights {
+            for (start, end) in TextUtils::find_char_ranges(line, highlight) {
+                ranges.push((start, end, highlight_style));
+            }
+        }
+        
+        // Add search highlighting
+        for index in self.matches_on_line(line_num) {
+            let search_match = &self.search_matches[index];
+            let style = if index == self.current_match {
+                Style::default().bg(Constants::CURRENT_MATCH_BG_COLOR).fg(Constants::CURRENT_MATCH_FG_COLOR)
+            } else {
+                Style::default().bg(Constants::OTHER_MATCH_BG_COLOR).fg(Constants::OTHER_MATCH_FG_COLOR)
+            };
+            
+            ranges.push((search_match.start, search_match.end, style));
+        }
+        
+        // Add selection highlighting on top of everything else
         if let Some(ref selection) = self.selection {
             if selection.contains_line(line_num) {
                 let (start_line, start_col, end_line, end_col) = selection.normalize();
@@ -572,36 +2351,14 @@ impl Viewer {
             }
         }
         
-        // Add search highlighting
-        let search_term = self.get_search_term();
-        if !search_term.is_empty() && line.contains(&search_term) {
-            let is_current_match = !self.search_matches.is_empty() && 
-                self.search_matches[self.current_match] == line_num;
-            
-            let mut match_count = 0;
-            for (start, part) in line.match_indices(&search_term) {
-                let end = start + TextUtils::char_len(part);
-                let style = if is_current_match && match_count == 0 {
-                    Style::default().bg(Constants::CURRENT_MATCH_BG_COLOR).fg(Constants::CURRENT_MATCH_FG_COLOR)
-                } else {
-                    Style::default().bg(Constants::OTHER_MATCH_BG_COLOR).fg(Constants::OTHER_MATCH_FG_COLOR)
-                };
-                
-                ranges.push((start, end, style));
-                match_count += 1;
-            }
-        }
-        
-        if ranges.is_empty() {
-            vec![Span::raw(line)]
-        } else {
-            TextUtils::split_line_into_spans(line, &ranges)
-        }
+        (ranges, end_state)
     }
     
     fn draw_progress_bar(&self, f: &mut Frame, area: Rect) {
+        // Loading the file can't be cancelled, searching and formatting can
+        let title = if self.search_task.is_some() || self.format_task.is_some() { "Progress, esc: cancel" } else { "Progress" };
         let progress = Gauge::default()
-            .block(Block::default().borders(Borders::ALL).title("Progress"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .gauge_style(Style::default().fg(Constants::PROGRESS_BAR_FG_COLOR).bg(Constants::PROGRESS_BAR_BG_COLOR))
             .ratio(self.progress_value)
             .label(format!("{:.1}% - {}", self.progress_value * 100.0, self.progress_message));
@@ -609,20 +2366,39 @@ impl Viewer {
         f.render_widget(progress, area);
     }
     
+    fn draw_format_error_banner(&self, f: &mut Frame, area: Rect, error: &SyntaxError) {
+        let hint = if self.showing_formatted {
+            "formatted up to the error, f: raw file"
+        } else {
+            "showing the raw file, f: formatted up to the error"
+        };
+        let banner = Paragraph::new(format!(" {} | {}", error, hint))
+            .style(Style::default().bg(Constants::FORMAT_ERROR_BG_COLOR).fg(Constants::FORMAT_ERROR_FG_COLOR));
+        f.render_widget(banner, area);
+    }
+    
     fn draw_status_bar(&self, f: &mut Frame, area: Rect) {
-        if self.in_search_mode {
-            // In search mode, show the TextArea for input
-            let search_area = Rect {
-                x: area.x,
-                y: area.y,
-                width: area.width,
-                height: area.height,
+        if let Some(kind) = self.prompt {
+            // In a prompt, show the label followed by the TextArea for input
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(kind.label().len() as u16), Constraint::Min(0)])
+                .split(area);
+            
+            let label = Paragraph::new(kind.label())
+                .style(Style::default().bg(Constants::STATUS_BAR_BG_COLOR).fg(Constants::STATUS_BAR_FG_COLOR));
+            f.render_widget(label, chunks[0]);
+            
+            let textarea = match kind {
+                PromptKind::Search => &self.search_textarea,
+                PromptKind::Goto | PromptKind::Column | PromptKind::Save | PromptKind::Pipe => &self.prompt_textarea,
+                PromptKind::Query => &self.query_textarea,
             };
-            f.render_widget(self.search_textarea.widget(), search_area);
+            f.render_widget(textarea.widget(), chunks[1]);
         } else {
             // Normal mode, show status information
             let total_lines = self.file_reader.line_count();
-            let current_pos = self.current_line + 1;
+            let current_pos = self.cursor.0 + 1;
             let match_info = if !self.search_matches.is_empty() {
                 format!(" | Match {}/{}", self.current_match + 1, self.search_matches.len())
             } else if !self.last_search_term.is_empty() {
@@ -630,13 +2406,48 @@ impl Viewer {
             } else {
                 String::new()
             };
-            let esc_hint = if !self.search_matches.is_empty() || !self.last_search_term.is_empty() {
+            let esc_hint = if !self.search_matches.is_empty() || !self.last_search_term.is_empty() || !self.highlights.is_empty() {
                 ", esc: clear search"
             } else {
                 ""
             };
-            let status = format!("Line {}/{} | q: quit, /: search, n: next match, g: start, G: end{}{}", 
-                               current_pos, total_lines, match_info, esc_hint);
+            let count_info = match self.pending_count {
+                Some(count) => format!(" | {}", count),
+                None => String::new(),
+            };
+            let message = match self.status_message {
+                Some(ref message) => format!(" | {}", message),
+                None => String::new(),
+            };
+            let view_info = match self.table {
+                Some(ref table) => {
+                    let column_count = table.header(&self.file_reader).len();
+                    let mut info = format!(" [table] column {}/{}", table.first_column() + 1, column_count);
+                    if table.hidden_count() > 0 {
+                        let _ = write!(info, ", {} hidden", table.hidden_count());
+                    }
+                    info
+                }
+                None if self.showing_formatted => " [formatted]".to_string(),
+                None => String::new(),
+            };
+            let filter_info = if self.is_filtered() { " [filtered]" } else { "" };
+            let visual_info = match self.visual {
+                Some(VisualMode::Char) => " [visual] y: yank",
+                Some(VisualMode::Line) => " [visual line] y: yank",
+                None => "",
+            };
+            let path_info = match self.line_path {
+                Some((_, ref path)) => format!(" | {}", path),
+                None => String::new(),
+            };
+            let format_hint = match self.format_kind {
+                Some(FormatKind::JsonLines) => "e: expand record",
+                Some(FormatKind::Csv | FormatKind::Tsv) => "t: table",
+                _ => "f: formatted",
+            };
+            let status = format!("Line {}/{}{}{}{}{}{}{} | q: quit, /: search, n: next match, :: goto, {}, g: start, G: end{}{}", 
+                               current_pos, total_lines, view_info, filter_info, visual_info, path_info, count_info, message, format_hint, match_info, esc_hint);
 
             let paragraph = Paragraph::new(status)
                 .style(Style::default().bg(Constants::STATUS_BAR_BG_COLOR).fg(Constants::STATUS_BAR_FG_COLOR));
@@ -645,47 +2456,23 @@ impl Viewer {
     }
     
     fn draw_context_menu(&self, f: &mut Frame, menu: &ContextMenu) {
-        let menu_height = menu.items.len() as u16;
-        
-        let screen_width = f.size().width;
-        let screen_height = f.size().height;
-        
-        let menu_x = if menu.x + Constants::CONTEXT_MENU_WIDTH > screen_width {
-            screen_width.saturating_sub(Constants::CONTEXT_MENU_WIDTH)
-        } else {
-            menu.x
-        };
+        let menu_area = menu.area(f.size());
         
-        let menu_y = if menu.y + menu_height > screen_height {
-            screen_height.saturating_sub(menu_height)
-        } else {
-            menu.y
-        };
-        
-        let menu_area = Rect {
-            x: menu_x,
-            y: menu_y,
-            width: Constants::CONTEXT_MENU_WIDTH,
-            height: menu_height,
-        };
-        
-        let background = Block::default()
-            .style(Style::default().bg(Constants::CONTEXT_MENU_BG_COLOR));
-        f.render_widget(background, menu_area);
-        
-        for (i, item) in menu.items.iter().enumerate() {
+        for (i, item) in menu.items.iter().enumerate().take(menu_area.height as usize) {
             let item_area = Rect {
-                x: menu_x,
-                y: menu_y + i as u16,
-                width: Constants::CONTEXT_MENU_WIDTH,
+                x: menu_area.x,
+                y: menu_area.y + i as u16,
+                width: menu_area.width,
                 height: 1,
             };
             
-            let item_text = format!("{:<width$}", item, width = Constants::CONTEXT_MENU_WIDTH as usize);
-            let item_paragraph = Paragraph::new(item_text)
-                .style(Style::default().bg(Constants::CONTEXT_MENU_BG_COLOR).fg(Constants::CONTEXT_MENU_FG_COLOR));
-            
-            f.render_widget(item_paragraph, item_area);
+            let style = if i == menu.selected {
+                Style::default().bg(Constants::CONTEXT_MENU_SELECTED_BG_COLOR).fg(Constants::CONTEXT_MENU_SELECTED_FG_COLOR)
+            } else {
+                Style::default().bg(Constants::CONTEXT_MENU_BG_COLOR).fg(Constants::CONTEXT_MENU_FG_COLOR)
+            };
+            let item_text = format!(" {:<width$}", item.label(), width = menu_area.width.saturating_sub(1) as usize);
+            f.render_widget(Paragraph::new(item_text).style(style), item_area);
         }
     }
 }
\ No newline at end of file
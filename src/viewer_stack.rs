@@ -0,0 +1,97 @@
+use crate::app_event::AppEvent;
+use crate::event_handler::EventHandler;
+use crate::file_reader::FileReader;
+use crate::viewer::{Viewer, ViewerAction};
+
+/// The viewer on screen, along with the viewers hidden behind the output of piped commands.
+///
+/// A hidden viewer's background tasks keep running, so an event from a task goes to the
+/// viewer that started it rather than to the one on screen.
+pub struct ViewerStack {
+    shown: Viewer,
+    hidden: Vec<Viewer>,
+}
+
+impl ViewerStack {
+    pub fn new(viewer: Viewer) -> Self {
+        Self { shown: viewer, hidden: Vec::new() }
+    }
+    
+    pub fn shown(&mut self) -> &mut Viewer {
+        &mut self.shown
+    }
+    
+    /// Handle an event. `q` in a command's output goes back to the viewer behind it, so only
+    /// leaving the last viewer quits.
+    pub fn handle_app_event(&mut self, event: AppEvent) -> ViewerAction {
+        let owner = event
+            .task_id()
+            .and_then(|task_id| self.hidden.iter_mut().find(|viewer| viewer.owns_task(task_id)))
+            .unwrap_or(&mut self.shown);
+        
+        match EventHandler::handle_app_event(owner, event) {
+            ViewerAction::Quit => match self.hidden.pop() {
+                Some(hidden) => {
+                    let output = std::mem::replace(&mut self.shown, hidden);
+                    self.shown.close_output(output);
+                    ViewerAction::None
+                }
+                None => ViewerAction::Quit,
+            },
+            ViewerAction::ShowOutput { command, file_reader } => {
+                self.show_output(&command, file_reader);
+                ViewerAction::None
+            }
+            ViewerAction::None => ViewerAction::None,
+        }
+    }
+    
+    fn show_output(&mut self, command: &str, file_reader: FileReader) {
+        let output = self.shown.open_output(command, file_reader);
+        self.hidden.push(std::mem::replace(&mut self.shown, output));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::TempFile;
+    use crate::formatter::FormatKind;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use std::sync::mpsc;
+    
+    #[test]
+    fn test_hidden_viewer_keeps_its_tasks() {
+        let file = TempFile::new("{\"a\": [1, 2], \"b\": {\"c\": null}}");
+        let formatted = TempFile::new("");
+        let (events, event_rx) = mpsc::channel();
+        let mut viewer = Viewer::new(file.reader(), events);
+        viewer.set_format_kind(Some(FormatKind::Json), true);
+        viewer.set_format_output(Some(formatted.path().to_path_buf()));
+        viewer.toggle_formatted_view();
+        assert!(viewer.is_formatting());
+        
+        // The formatted copy is finished while a command's output is on screen
+        let mut stack = ViewerStack::new(viewer);
+        let output = TempFile::new("one\ntwo");
+        stack.show_output("cat", output.reader());
+        for event in event_rx.iter() {
+            let formatted = matches!(event, AppEvent::Formatted { .. });
+            stack.handle_app_event(event);
+            if formatted {
+                break;
+            }
+        }
+        assert!(!stack.hidden[0].is_formatting());
+        
+        let quit = AppEvent::Input(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert!(matches!(stack.handle_app_event(quit), ViewerAction::None));
+        assert!(stack.hidden.is_empty());
+        
+        // Back on the original viewer, the copy is kept and toggling needs no new formatting
+        let viewer = stack.shown();
+        assert!(!viewer.is_formatting());
+        viewer.toggle_formatted_view();
+        assert!(!viewer.is_formatting());
+    }
+}
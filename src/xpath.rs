@@ -0,0 +1,324 @@
+use crate::file_reader::{FileReader, SearchMatch};
+use crate::structure::{BlockKind, Node, StructureIndex};
+use anyhow::{bail, Result};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Which nodes a step looks at, relative to each context node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    /// `/name`
+    Child,
+    /// `//name`
+    Descendant,
+}
+
+/// A filter applied to the elements a step selects under each parent
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Predicate {
+    /// `[2]`, counting from 1
+    Position(usize),
+    /// `[last()]`
+    Last,
+    /// `[@id]`
+    HasAttribute(String),
+    /// `[@id='x']`
+    AttributeEquals(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    axis: Axis,
+    /// Element name, or `None` for `*`
+    name: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+/// An absolute XPath expression using child and descendant steps with attribute and
+/// positional predicates, evaluated against the line structure of a pretty-printed document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPath {
+    steps: Vec<Step>,
+}
+
+impl XPath {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let mut rest = expression.trim();
+        if !rest.starts_with('/') {
+            bail!("XPath must start with / or //");
+        }
+        
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            let (axis, after) = match rest.strip_prefix("//") {
+                Some(after) => (Axis::Descendant, after),
+                None => match rest.strip_prefix('/') {
+                    Some(after) => (Axis::Child, after),
+                    None => bail!("Expected / at '{}'", rest),
+                },
+            };
+            
+            let end = after.find(['/', '[']).unwrap_or(after.len());
+            let name = match after[..end].trim() {
+                "" => bail!("Expected an element name at '{}'", after),
+                "*" => None,
+                name if name.starts_with('@') => bail!("Attribute steps are not supported, use a predicate such as [{}]", name),
+                name if !StructureIndex::is_element_name(name) => bail!("Unsupported step '{}'", name),
+                name => Some(name.to_string()),
+            };
+            rest = &after[end..];
+            
+            let mut predicates = Vec::new();
+            while let Some(after) = rest.strip_prefix('[') {
+                let (predicate, remaining) = Self::parse_predicate(after)?;
+                predicates.push(predicate);
+                rest = remaining;
+            }
+            
+            steps.push(Step { axis, name, predicates });
+        }
+        
+        Ok(Self { steps })
+    }
+    
+    /// Parse the inside of `[...]`, returning the predicate and the text after the `]`
+    fn parse_predicate(text: &str) -> Result<(Predicate, &str)> {
+        // The closing bracket is the first one outside a quoted value
+        let mut quote = None;
+        let Some(end) = text.char_indices().find_map(|(i, c)| {
+            match quote {
+                Some(open) if c == open => quote = None,
+                Some(_) => {}
+                None if c == '\'' || c == '"' => quote = Some(c),
+                None if c == ']' => return Some(i),
+                None => {}
+            }
+            None
+        }) else {
+            bail!("Missing ]");
+        };
+        let inner = text[..end].trim();
+        let rest = &text[end + 1..];
+        
+        if inner == "last()" {
+            return Ok((Predicate::Last, rest));
+        }
+        if let Ok(position) = inner.parse::<usize>() {
+            if position == 0 {
+                bail!("Positions count from 1");
+            }
+            return Ok((Predicate::Position(position), rest));
+        }
+        
+        if let Some(attribute) = inner.strip_prefix('@') {
+            let Some((name, value)) = attribute.split_once('=') else {
+                if StructureIndex::is_element_name(attribute) {
+                    return Ok((Predicate::HasAttribute(attribute.to_string()), rest));
+                }
+                bail!("Unsupported predicate [{}]", inner);
+            };
+            let value = value.trim();
+            let unquoted = value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+                .or_else(|| value.strip_prefix('"').and_then(|value| value.strip_suffix('"')));
+            if let Some(unquoted) = unquoted.filter(|_| StructureIndex::is_element_name(name.trim())) {
+                return Ok((Predicate::AttributeEquals(name.trim().to_string(), unquoted.to_string()), rest));
+            }
+        }
+        
+        bail!("Unsupported predicate [{}]: use a position, last(), [@name] or [@name='value']", inner)
+    }
+    
+    /// Every element the expression selects, as matches spanning each element's first line.
+    /// Returns `None` if cancelled part way.
+    pub fn evaluate(&self, structure: &StructureIndex, reader: &FileReader, cancel: &AtomicBool) -> Option<Vec<SearchMatch>> {
+        // `None` stands for the document itself, whose children are the top level nodes
+        let mut contexts: Vec<Option<Node>> = vec![None];
+        
+        for step in &self.steps {
+            let mut selected = Vec::new();
+            for &context in &contexts {
+                let parents: Vec<Option<Node>> = match (step.axis, context) {
+                    (Axis::Child, _) => vec![context],
+                    (Axis::Descendant, None) => std::iter::once(None)
+                        .chain((0..structure.block_count()).map(|block| Some(Node::Block(block))))
+                        .collect(),
+                    (Axis::Descendant, Some(Node::Block(block))) => {
+                        structure.descendant_blocks(block).map(|block| Some(Node::Block(block))).collect()
+                    }
+                    (Axis::Descendant, Some(Node::Line(_))) => Vec::new(),
+                };
+                
+                for parent in parents {
+                    if cancel.load(Ordering::Relaxed) {
+                        return None;
+                    }
+                    selected.extend(Self::select(step, parent, structure, reader));
+                }
+            }
+            
+            // Descendant steps can reach the same element from several contexts
+            selected.sort_by_key(|&node| structure.start_line(node));
+            selected.dedup();
+            contexts = selected.into_iter().map(Some).collect();
+        }
+        
+        let matches = contexts
+            .into_iter()
+            .flatten()
+            .map(|node| {
+                let line = structure.start_line(node);
+                let text = reader.get_line(line).unwrap_or("");
+                let start = text.chars().take_while(|c| c.is_whitespace()).count();
+                SearchMatch { line, start, end: start + text.trim().chars().count() }
+            })
+            .collect();
+        Some(matches)
+    }
+    
+    /// The child elements of `parent` that pass a step's name test and predicates
+    fn select(step: &Step, parent: Option<Node>, structure: &StructureIndex, reader: &FileReader) -> Vec<Node> {
+        let mut candidates: Vec<Node> = Self::child_elements(parent, structure, reader)
+            .filter(|&(_, name)| step.name.as_deref().is_none_or(|wanted| wanted == name))
+            .map(|(node, _)| node)
+            .collect();
+        
+        for predicate in &step.predicates {
+            candidates = match predicate {
+                Predicate::Position(position) => candidates.get(position - 1).copied().into_iter().collect(),
+                Predicate::Last => candidates.last().copied().into_iter().collect(),
+                Predicate::HasAttribute(name) => candidates
+                    .into_iter()
+                    .filter(|&node| Self::attribute(reader.get_line(structure.start_line(node)).unwrap_or(""), name).is_some())
+                    .collect(),
+                Predicate::AttributeEquals(name, value) => candidates
+                    .into_iter()
+                    .filter(|&node| {
+                        Self::attribute(reader.get_line(structure.start_line(node)).unwrap_or(""), name).as_deref() == Some(value.as_str())
+                    })
+                    .collect(),
+            };
+        }
+        candidates
+    }
+    
+    /// The elements directly inside `parent`, or at the top level for `None`, with their names
+    fn child_elements<'a>(parent: Option<Node>, structure: &'a StructureIndex, reader: &'a FileReader) -> Box<dyn Iterator<Item = (Node, &'a str)> + 'a> {
+        let children: Box<dyn Iterator<Item = Node> + 'a> = match parent {
+            None => Box::new(structure.top_level(reader.line_count())),
+            Some(Node::Block(block)) if structure.block(block).kind == BlockKind::Element => Box::new(structure.children(block)),
+            Some(_) => Box::new(std::iter::empty()),
+        };
+        Box::new(children.filter_map(move |node| {
+            let name = StructureIndex::element_name(reader.get_line(structure.start_line(node))?)?;
+            Some((node, name))
+        }))
+    }
+    
+    /// The unescaped value of an attribute on the start tag a line begins with
+    fn attribute(line: &str, name: &str) -> Option<String> {
+        let mut reader = Reader::from_str(line.trim());
+        let start = match reader.read_event() {
+            Ok(Event::Start(start)) | Ok(Event::Empty(start)) => start,
+            _ => return None,
+        };
+        let attribute = start.try_get_attribute(name).ok()??;
+        attribute.unescape_value().ok().map(|value| value.into_owned())
+    }
+    
+    /// The element path of the element a line belongs to, such as `/feed/entry[3]/title`.
+    /// Positions are only shown where an element has siblings of the same name. Each step is
+    /// looked up in the index, so the cost doesn't grow with the number of siblings.
+    pub fn path_of_line(structure: &StructureIndex, reader: &FileReader, line: usize) -> String {
+        let mut segments = Vec::new();
+        let mut current = structure.node_at(line);
+        
+        // Text and comments inside an element belong to that element
+        if let Some((Node::Line(line), parent)) = current {
+            if reader.get_line(line).and_then(StructureIndex::element_name).is_none() {
+                current = parent.map(|parent| (Node::Block(parent), structure.block(parent).parent));
+            }
+        }
+        
+        while let Some((node, parent)) = current {
+            let Some(name) = reader.get_line(structure.start_line(node)).and_then(StructureIndex::element_name) else {
+                break;
+            };
+            
+            segments.push(match structure.element_position(structure.start_line(node)) {
+                Some((position, true)) => format!("{}[{}]", name, position + 1),
+                _ => name.to_string(),
+            });
+            
+            current = parent.map(|parent| (Node::Block(parent), structure.block(parent).parent));
+        }
+        
+        if segments.is_empty() {
+            return "/".to_string();
+        }
+        segments.iter().rev().fold(String::new(), |path, segment| path + "/" + segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::TempFile;
+    use crate::syntax::SyntaxKind;
+    
+    const DOCUMENT: &str = "<?xml version=\"1.0\"?>\n<catalog>\n    <book id=\"a\">\n        <title>One</title>\n    </book>\n    <book id=\"b&amp;c\">\n        <title>Two</title>\n        <note/>\n    </book>\n    <magazine>\n        text\n    </magazine>\n</catalog>";
+    
+    fn with_document(test: impl FnOnce(&StructureIndex, &FileReader)) {
+        let reader = TempFile::new(DOCUMENT).reader();
+        let structure = StructureIndex::build(&reader, SyntaxKind::Xml, &AtomicBool::new(false)).unwrap();
+        test(&structure, &reader);
+    }
+    
+    #[test]
+    fn test_parse() {
+        let path = XPath::parse("//book[@id='a'][1]/*").unwrap();
+        assert_eq!(path.steps[0], Step {
+            axis: Axis::Descendant,
+            name: Some("book".to_string()),
+            predicates: vec![Predicate::AttributeEquals("id".to_string(), "a".to_string()), Predicate::Position(1)],
+        });
+        assert_eq!(path.steps[1].name, None);
+        assert!(XPath::parse("book").is_err());
+        assert!(XPath::parse("/catalog/@id").is_err());
+        assert!(XPath::parse("/catalog[0]").is_err());
+        assert!(XPath::parse("/catalog[contains(., 'x')]").is_err());
+    }
+    
+    #[test]
+    fn test_evaluate() {
+        with_document(|structure, reader| {
+            let lines = |expression: &str| -> Vec<usize> {
+                let matches = XPath::parse(expression).unwrap().evaluate(structure, reader, &AtomicBool::new(false)).unwrap();
+                matches.into_iter().map(|m| m.line).collect()
+            };
+            assert_eq!(lines("/catalog"), vec![1]);
+            assert_eq!(lines("/catalog/book"), vec![2, 5]);
+            assert_eq!(lines("/catalog/book[2]/title"), vec![6]);
+            assert_eq!(lines("/catalog/book[last()]"), vec![5]);
+            assert_eq!(lines("//title"), vec![3, 6]);
+            assert_eq!(lines("//book[@id='b&c']/*"), vec![6, 7]);
+            assert_eq!(lines("//*[@id]"), vec![2, 5]);
+            assert_eq!(lines("/catalog/*[3]"), vec![9]);
+            assert!(lines("/book").is_empty());
+        });
+    }
+    
+    #[test]
+    fn test_path_of_line() {
+        with_document(|structure, reader| {
+            let path_of = |line| XPath::path_of_line(structure, reader, line);
+            assert_eq!(path_of(0), "/");
+            assert_eq!(path_of(1), "/catalog");
+            assert_eq!(path_of(6), "/catalog/book[2]/title");
+            assert_eq!(path_of(8), "/catalog/book[2]");
+            assert_eq!(path_of(10), "/catalog/magazine");
+        });
+    }
+}